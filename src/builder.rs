@@ -0,0 +1,217 @@
+use ga_macros::*;
+
+use crate::*;
+
+/*
+        Chains gates onto a circuit without spelling out every Instruction. Measurements are
+    numbered in the order they get added (starting from 0), which is the number `when`
+    expects, and the bodies of `when` and the loops keep counting from where the circuit
+    around them was. Every qubit index gets checked against the size of the circuit right away
+*/
+#[derive(Clone, Debug)]
+pub struct CircuitBuilder {
+    qubits: usize,
+    instructions: Vec<Instruction>,
+    measurements: usize
+}
+
+impl CircuitBuilder {
+    pub fn new(qubits: usize) -> Self {
+        CircuitBuilder { qubits, instructions: Vec::new(), measurements: 0 }
+    }
+
+    pub fn qubits(&self) -> usize {
+        self.qubits
+    }
+
+    // How many measurements have been added so far, the next one gets this number
+    pub fn measurements(&self) -> usize {
+        self.measurements
+    }
+
+    pub fn h(self, qubit: usize) -> Self { self.gate(&HADAMARD, &[qubit]) }
+    pub fn x(self, qubit: usize) -> Self { self.gate(&PAULIX, &[qubit]) }
+    pub fn y(self, qubit: usize) -> Self { self.gate(&PAULIY, &[qubit]) }
+    pub fn z(self, qubit: usize) -> Self { self.gate(&PAULIZ, &[qubit]) }
+    pub fn s(self, qubit: usize) -> Self { self.gate(&SGATE, &[qubit]) }
+    pub fn sdg(self, qubit: usize) -> Self { self.gate(&SDAGGER, &[qubit]) }
+    pub fn t(self, qubit: usize) -> Self { self.gate(&TGATE, &[qubit]) }
+    pub fn tdg(self, qubit: usize) -> Self { self.gate(&TDAGGER, &[qubit]) }
+    pub fn sx(self, qubit: usize) -> Self { self.gate(&SXGATE, &[qubit]) }
+
+    pub fn rx(self, theta: f64, qubit: usize) -> Self { self.gate(&rx(theta), &[qubit]) }
+    pub fn ry(self, theta: f64, qubit: usize) -> Self { self.gate(&ry(theta), &[qubit]) }
+    pub fn rz(self, theta: f64, qubit: usize) -> Self { self.gate(&rz(theta), &[qubit]) }
+    pub fn phase(self, theta: f64, qubit: usize) -> Self { self.gate(&phase(theta), &[qubit]) }
+    pub fn u3(self, theta: f64, phi: f64, lambda: f64, qubit: usize) -> Self { self.gate(&u3(theta, phi, lambda), &[qubit]) }
+
+    pub fn cx(self, control: usize, target: usize) -> Self { self.gate(&CNOT, &[control, target]) }
+    pub fn cz(self, control: usize, target: usize) -> Self { self.gate(&CZ, &[control, target]) }
+    pub fn swap(self, a: usize, b: usize) -> Self { self.gate(&SWAP, &[a, b]) }
+
+    // Any gate, qubits[0] lines up with the gate's most significant qubit
+    pub fn gate(mut self, gate: &Vec<Vec<t!()>>, qubits: &[usize]) -> Self {
+        for (k, &qubit) in qubits.iter().enumerate() {
+            self.check(qubit);
+
+            if qubits[..k].contains(&qubit) { panic!("qubit {} is used twice by the same gate", qubit) };
+        }
+
+        let (matrix, index) = embed_gate(gate, qubits);
+
+        self.instructions.push(Instruction::Gate(matrix, index));
+        self
+    }
+
+    pub fn measure(self, qubit: usize) -> Self {
+        self.push_measurement(Instruction::Measure(qubit, true), qubit)
+    }
+
+    pub fn measure_hidden(self, qubit: usize) -> Self {
+        self.push_measurement(Instruction::Measure(qubit, false), qubit)
+    }
+
+    pub fn measure_at_angle(self, angle: f64, qubit: usize) -> Self {
+        self.push_measurement(Instruction::MeasureAtAngle(angle, qubit, true), qubit)
+    }
+
+    // Shows up in the results as `label=1` rather than the qubit's number
+    pub fn measure_as(self, qubit: usize, label: &str) -> Self {
+        self.push_measurement(Instruction::Labeled(label.to_string(), Box::new(Instruction::Measure(qubit, true))), qubit)
+    }
+
+    // Measures every qubit of the register in order, labeling them alice[0], alice[1], ...
+    pub fn measure_register(self, register: &Register) -> Self {
+        (0..register.len()).fold(self, |builder, i| builder.measure_as(register.qubit(i), &register.label(i)))
+    }
+
+    // Runs whatever body adds only if the given measurement came out as 1
+    pub fn when<F: FnOnce(CircuitBuilder) -> CircuitBuilder>(mut self, measurement: usize, body: F) -> Self {
+        if measurement >= self.measurements {
+            panic!("measurement {} hasn't happened yet, only {} have", measurement, self.measurements)
+        };
+
+        let body = body(self.body()).build();
+
+        self.instructions.push(Instruction::Dependent(Box::new(Instruction::Circuit(body, 0)), measurement));
+        self
+    }
+
+    /*
+            Loops whose condition gets checked against the measurement numbers as they stand
+        at that point. The body's measurements are numbered on from the ones before the loop,
+        are only around for the check that follows them, and never show up in the results
+    */
+    pub fn while_loop<F: FnOnce(CircuitBuilder) -> CircuitBuilder>(mut self, condition: Condition, max_iter: usize, body: F) -> Self {
+        let body = self.loop_body(&condition, body);
+
+        self.instructions.push(Instruction::While(condition, Box::new(body), max_iter));
+        self
+    }
+
+    pub fn repeat_until<F: FnOnce(CircuitBuilder) -> CircuitBuilder>(mut self, body: F, condition: Condition) -> Self {
+        let body = self.loop_body(&condition, body);
+
+        self.instructions.push(Instruction::RepeatUntil(Box::new(body), condition));
+        self
+    }
+
+    // Inserts a whole circuit with its qubit 0 placed at offset
+    pub fn sub(mut self, circuit: &Vec<Instruction>, offset: usize) -> Self {
+        let width = circuit.iter().map(|instruction| instruction.width()).max().unwrap_or(0);
+
+        if offset + width > self.qubits {
+            panic!("a circuit on {} qubits doesn't fit at offset {} of a {} qubit circuit", width, offset, self.qubits)
+        };
+
+        self.measurements += circuit.iter().map(|instruction| instruction.measurements()).sum::<usize>();
+        self.instructions.push(Instruction::Circuit(circuit.clone(), offset));
+        self
+    }
+
+    // Runs the circuit placed at offset the given number of times in a row
+    pub fn repeat(mut self, circuit: &Vec<Instruction>, offset: usize, times: usize) -> Self {
+        let instruction = Instruction::Repeat(Box::new(Instruction::Circuit(circuit.clone(), offset)), times);
+
+        if instruction.width() > self.qubits {
+            panic!("a circuit on {} qubits doesn't fit at offset {} of a {} qubit circuit", instruction.width() - offset, offset, self.qubits)
+        };
+
+        self.measurements += instruction.measurements();
+        self.instructions.push(instruction);
+        self
+    }
+
+    // Undoes a circuit placed at offset, like uncomputing an oracle
+    pub fn sub_inverse(self, circuit: &Vec<Instruction>, offset: usize) -> Self {
+        self.sub(&inverse_circuit(circuit), offset)
+    }
+
+    // Prepares the state (from |0...0⟩) on the qubits starting at offset
+    pub fn prepare(self, state: &Vec<t!()>, offset: usize) -> Self {
+        self.sub(&prepare_state(state), offset)
+    }
+
+    // The circuit placed at offset, run only when every control is in its ctrl_state
+    pub fn controlled(mut self, circuit: &Vec<Instruction>, offset: usize, controls: &[usize], ctrl_state: &[bool]) -> Self {
+        controls.iter().for_each(|&control| self.check(control));
+
+        let instruction = Instruction::Circuit(circuit.clone(), offset).controlled(controls.to_vec(), ctrl_state.to_vec());
+
+        if instruction.width() > self.qubits {
+            panic!("a controlled circuit on {} qubits doesn't fit in a {} qubit circuit", instruction.width(), self.qubits)
+        };
+
+        self.instructions.push(instruction);
+        self
+    }
+
+    pub fn build(self) -> Vec<Instruction> {
+        self.instructions
+    }
+
+    // Starts every qubit off in |0⟩
+    pub fn experiment(self) -> Experiment {
+        Experiment::new(vec![ZERO.clone(); self.qubits], self.instructions)
+    }
+
+    pub fn experiment_from(self, qbits: Vec<Vec<t!()>>) -> Experiment {
+        if qbits.len() != self.qubits { panic!("expected {} qubits but got {}", self.qubits, qbits.len()) };
+
+        Experiment::new(qbits, self.instructions)
+    }
+
+    pub fn experiment_from_state(self, state: Vec<t!()>) -> Experiment {
+        if state.len() != 1 << self.qubits { panic!("expected a state of length {} but got {}", 1 << self.qubits, state.len()) };
+
+        Experiment::from_state(state, self.instructions)
+    }
+
+    fn push_measurement(mut self, instruction: Instruction, qubit: usize) -> Self {
+        self.check(qubit);
+
+        self.instructions.push(instruction);
+        self.measurements += 1;
+        self
+    }
+
+    fn loop_body<F: FnOnce(CircuitBuilder) -> CircuitBuilder>(&self, condition: &Condition, body: F) -> Instruction {
+        let body = body(self.body());
+
+        if condition.span() > body.measurements {
+            panic!("the loop condition looks at measurement {} which never happens", condition.span() - 1)
+        };
+
+        Instruction::Circuit(body.build(), 0)
+    }
+
+    // Conditional and loop bodies number their measurements on from the ones before them,
+    // and can depend on any of those
+    fn body(&self) -> CircuitBuilder {
+        CircuitBuilder { measurements: self.measurements, ..CircuitBuilder::new(self.qubits) }
+    }
+
+    fn check(&self, qubit: usize) {
+        if qubit >= self.qubits { panic!("qubit {} is out of range for a {} qubit circuit", qubit, self.qubits) };
+    }
+}
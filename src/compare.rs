@@ -0,0 +1,194 @@
+use ga_macros::*;
+use rand::Rng;
+
+use crate::tensor::*;
+use crate::linalg::*;
+use crate::{Experiment, Instruction, inverse_circuit};
+
+// Circuits on up to this many qubits get their whole unitaries compared
+const EXACT_QUBITS: usize = 8;
+
+// How far from 1 a fidelity (or from each other two phases) can be and still count as equal
+const EQUIVALENCE_TOLERANCE: f64 = 1e-9;
+
+// |⟨ψ|φ⟩|², both states get normalized first
+pub fn fidelity(psi: &Vec<t!()>, phi: &Vec<t!()>) -> f64 {
+    let overlap = inner_product(psi, phi);
+    let psi_len = inner_product(psi, psi)[0];
+    let phi_len = inner_product(phi, phi)[0];
+
+    if psi_len == 0.0 || phi_len == 0.0 { panic!("can't take the fidelity with a zero vector, it has no direction to compare") };
+
+    (overlap[0].powf(2.0) + overlap[1].powf(2.0)) / (psi_len * phi_len)
+}
+
+// ½ tr|ρ - σ| for density matrices
+pub fn trace_distance(rho: &Vec<Vec<t!()>>, sigma: &Vec<Vec<t!()>>) -> f64 {
+    if rho.len() != sigma.len() { panic!("density matrices should be the same size") };
+
+    let difference: Vec<Vec<t!()>> = rho.iter().zip(sigma.iter()).map(|(r, s)| {
+        r.iter().zip(s.iter()).map(|(a, b)| eq!(a - b)).collect()
+    }).collect();
+
+    hermitian_eigen(&difference).0.iter().map(|l| l.abs()).sum::<f64>() / 2.0
+}
+
+pub fn equal_up_to_global_phase(psi: &Vec<t!()>, phi: &Vec<t!()>, tolerance: f64) -> bool {
+    psi.len() == phi.len() && match global_phase(psi, phi) {
+        Some(phase) => psi.iter().zip(phi.iter()).all(|(a, b)| {
+            let difference = eq!(a * phase - b);
+
+            difference[0].abs() <= tolerance && difference[1].abs() <= tolerance
+        }),
+        None => psi.iter().chain(phi.iter()).all(|z| z[0].abs() <= tolerance && z[1].abs() <= tolerance)
+    }
+}
+
+pub fn unitary_equiv_up_to_phase(u: &Vec<Vec<t!()>>, v: &Vec<Vec<t!()>>, tolerance: f64) -> bool {
+    u.len() == v.len() && equal_up_to_global_phase(
+        &u.iter().flatten().copied().collect(),
+        &v.iter().flatten().copied().collect(),
+        tolerance
+    )
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Equivalence {
+    Equivalent,
+    /*
+            input is a basis state ("01" style, qubit 0 first) the circuits treat differently,
+        along with the fidelity between the two outputs for it. A fidelity of 1 means the
+        outputs only differ in phase compared to the other inputs'
+    */
+    Different { input: String, fidelity: f64 }
+}
+
+impl Equivalence {
+    pub fn is_equivalent(&self) -> bool {
+        *self == Equivalence::Equivalent
+    }
+}
+
+/*
+        Decides whether two measurement free circuits do the same thing up to a global phase.
+    Small ones get their unitaries compared outright, and on a mismatch the basis states get
+    tried in order until one comes out differently. Bigger ones are run on -log2(1 - confidence)
+    random states. A difference that at least half of all states show above the tolerance
+    (anything acting on whole qubits, like a gate that's missing somewhere) then slips through
+    with probability at most 1 - confidence. There's no such guarantee for a difference confined
+    to a few basis states: a random state only shows it around 2^-n, under the tolerance for
+    big circuits, whatever the confidence. When a random state ψ does come out differently,
+    A⁻¹(Aψ - e^iφ Bψ) (with the phase lined up) is what B changes about ψ, and the basis state
+    it's largest on is reported
+*/
+pub fn check_equivalence(a: &Vec<Instruction>, b: &Vec<Instruction>, qubits: usize, confidence: f64) -> Equivalence {
+    check_equivalence_with(a, b, qubits, confidence, &mut rand::thread_rng())
+}
+
+pub fn check_equivalence_with<R: Rng>(a: &Vec<Instruction>, b: &Vec<Instruction>, qubits: usize, confidence: f64, rng: &mut R) -> Equivalence {
+    let inverse = Experiment::new(vec![ZERO.clone(); qubits], inverse_circuit(a));
+    let a = Experiment::new(vec![ZERO.clone(); qubits], a.clone());
+    let b = Experiment::new(vec![ZERO.clone(); qubits], b.clone());
+
+    if qubits <= EXACT_QUBITS {
+        let equivalent = unitary_equiv_up_to_phase(&a.unitary(), &b.unitary(), EQUIVALENCE_TOLERANCE.sqrt());
+
+        return if equivalent { Equivalence::Equivalent } else { distinguishing_input(&a, &b, qubits) };
+    }
+
+    let trials = (-(1.0 - confidence).log2()).ceil().max(1.0) as usize;
+
+    for _ in 0..trials {
+        let input = random_state(1 << qubits, rng);
+        let (mut u, mut v) = (input.clone(), input);
+
+        a.evolve(&mut u);
+        b.evolve(&mut v);
+
+        if fidelity(&u, &v) > 1.0 - EQUIVALENCE_TOLERANCE { continue };
+
+        let overlap = inner_product(&v, &u);
+        let magnitude = (overlap[0].powf(2.0) + overlap[1].powf(2.0)).sqrt();
+        let phase = if magnitude > 0.0 { eq!(overlap / #magnitude) } else { eq!(1) };
+
+        let mut difference: Vec<t!()> = u.iter().zip(v.iter()).map(|(x, y)| eq!(x - phase * y)).collect();
+
+        inverse.evolve(&mut difference);
+
+        let weight = |z: &t!()| z[0].powf(2.0) + z[1].powf(2.0);
+        let k = (0..difference.len()).max_by(|&i, &j| weight(&difference[i]).partial_cmp(&weight(&difference[j])).unwrap()).unwrap();
+
+        let mut basis = vec![eq!(0); 1 << qubits];
+        basis[k] = eq!(1);
+
+        let (mut u, mut v) = (basis.clone(), basis);
+
+        a.evolve(&mut u);
+        b.evolve(&mut v);
+
+        return Equivalence::Different { input: format!("{:0width$b}", k, width = qubits), fidelity: fidelity(&u, &v) };
+    }
+
+    Equivalence::Equivalent
+}
+
+fn distinguishing_input(a: &Experiment, b: &Experiment, qubits: usize) -> Equivalence {
+    let mut reference: Option<t!()> = None;
+    let mut worst = (0.0, 0, 1.0);
+
+    for k in 0..(1 << qubits) {
+        let mut u = vec![eq!(0); 1 << qubits];
+        u[k] = eq!(1);
+
+        let mut v = u.clone();
+
+        a.evolve(&mut u);
+        b.evolve(&mut v);
+
+        let overlap = inner_product(&u, &v);
+        let fidelity = overlap[0].powf(2.0) + overlap[1].powf(2.0);
+        let magnitude = fidelity.sqrt();
+        let phase = eq!(overlap / #magnitude);
+        let reference = *reference.get_or_insert(phase);
+        let drift = eq!(phase - reference);
+
+        let score = (1.0 - fidelity).max(drift[0].powf(2.0) + drift[1].powf(2.0));
+
+        if score > EQUIVALENCE_TOLERANCE {
+            return Equivalence::Different { input: format!("{:0width$b}", k, width = qubits), fidelity };
+        }
+
+        if score > worst.0 { worst = (score, k, fidelity) };
+    }
+
+    Equivalence::Different { input: format!("{:0width$b}", worst.1, width = qubits), fidelity: worst.2 }
+}
+
+// Normalized state with gaussian amplitudes, which makes it uniformly random
+fn random_state<R: Rng>(len: usize, rng: &mut R) -> Vec<t!()> {
+    let mut state: Vec<t!()> = (0..len).map(|_| {
+        let (r, theta) = ((-2.0 * (1.0 - rng.gen::<f64>()).ln()).sqrt(), 2.0 * std::f64::consts::PI * rng.gen::<f64>());
+
+        [r * theta.cos(), r * theta.sin()]
+    }).collect();
+
+    norm(&mut state);
+
+    state
+}
+
+// The unit phase e^iθ that takes psi's largest component onto the matching one in phi
+fn global_phase(psi: &Vec<t!()>, phi: &Vec<t!()>) -> Option<t!()> {
+    let magnitude = |z: &t!()| z[0].powf(2.0) + z[1].powf(2.0);
+
+    let index = (0..psi.len()).max_by(|&i, &j| magnitude(&psi[i]).partial_cmp(&magnitude(&psi[j])).unwrap())?;
+
+    if magnitude(&psi[index]) == 0.0 || magnitude(&phi[index]) == 0.0 { return None };
+
+    let a = psi[index];
+    let b = phi[index];
+    let ratio = eq!(b / a);
+    let len = magnitude(&ratio).powf(0.5);
+
+    Some(eq!(ratio / #len))
+}
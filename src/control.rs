@@ -0,0 +1,44 @@
+/*
+        A test on measurement results for While and RepeatUntil. Measurements are numbered
+    the same way Dependent numbers them, and one that hasn't happened yet (like the loop
+    body's before its first run) reads as 0
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    One(usize),
+    Zero(usize),
+    Not(Box<Condition>),
+    All(Vec<Condition>),
+    Any(Vec<Condition>)
+}
+
+impl Condition {
+    pub fn holds(&self, results: &[bool]) -> bool {
+        match self {
+            Condition::One(measurement) => *results.get(*measurement).unwrap_or(&false),
+            Condition::Zero(measurement) => !*results.get(*measurement).unwrap_or(&false),
+            Condition::Not(condition) => !condition.holds(results),
+            Condition::All(conditions) => conditions.iter().all(|condition| condition.holds(results)),
+            Condition::Any(conditions) => conditions.iter().any(|condition| condition.holds(results))
+        }
+    }
+
+    // One past the highest measurement it looks at
+    pub fn span(&self) -> usize {
+        match self {
+            Condition::One(measurement) | Condition::Zero(measurement) => measurement + 1,
+            Condition::Not(condition) => condition.span(),
+            Condition::All(conditions) | Condition::Any(conditions) => conditions.iter().map(|condition| condition.span()).max().unwrap_or(0)
+        }
+    }
+
+    pub fn shifted(&self, by: usize) -> Condition {
+        match self {
+            Condition::One(measurement) => Condition::One(measurement + by),
+            Condition::Zero(measurement) => Condition::Zero(measurement + by),
+            Condition::Not(condition) => Condition::Not(Box::new(condition.shifted(by))),
+            Condition::All(conditions) => Condition::All(conditions.iter().map(|condition| condition.shifted(by)).collect()),
+            Condition::Any(conditions) => Condition::Any(conditions.iter().map(|condition| condition.shifted(by)).collect())
+        }
+    }
+}
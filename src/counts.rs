@@ -0,0 +1,333 @@
+use std::collections::BTreeMap;
+
+use rand::Rng;
+
+use crate::Measurement;
+
+// "0110" style key, one character per measurement in the order they were taken
+pub fn bitstring(measurements: &Vec<Measurement>) -> String {
+    measurements.iter().map(|m| if m.1 { '1' } else { '0' }).collect()
+}
+
+// The qubit and label behind each position of a bitstring
+type Sequence = (Vec<usize>, Vec<Option<String>>);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Counts {
+    counts: BTreeMap<String, usize>,
+    qubits: Vec<usize>,
+    labels: Vec<Option<String>>,
+    // What each recorded bitstring measured, shots with conditional measurements don't all
+    // measure the same qubits
+    measured: BTreeMap<String, Sequence>,
+    // Loop number => iterations => how many times the loop went round that often
+    iterations: BTreeMap<usize, BTreeMap<usize, usize>>,
+    gave_up: BTreeMap<usize, usize>,
+    shots: usize
+}
+
+impl Counts {
+    pub fn new() -> Self {
+        Counts::default()
+    }
+
+    pub fn with_qubits(qubits: Vec<usize>) -> Self {
+        Counts { labels: vec![None; qubits.len()], qubits, ..Counts::default() }
+    }
+
+    pub fn with_labels(qubits: Vec<usize>, labels: Vec<Option<String>>) -> Self {
+        if labels.len() != qubits.len() { panic!("got {} labels for {} qubits", labels.len(), qubits.len()) };
+
+        Counts { qubits, labels, ..Counts::default() }
+    }
+
+    pub fn record(&mut self, measurements: &Vec<Measurement>) {
+        let key = bitstring(measurements);
+
+        self.note(&key, &(measurements.iter().map(|m| m.0).collect(), measurements.iter().map(|m| m.2.clone()).collect()));
+        self.add(key, 1);
+    }
+
+    // Keeps track of what the bitstring measured, the longest sequence so far being the default
+    fn note(&mut self, key: &str, sequence: &Sequence) {
+        if sequence.0.len() > self.qubits.len() {
+            self.qubits = sequence.0.clone();
+            self.labels = sequence.1.clone();
+        }
+
+        match self.measured.get(key) {
+            Some(known) if known != sequence => {
+                panic!("the bitstring {} came from measuring qubits {:?} and {:?} in different shots", key, known.0, sequence.0)
+            },
+            Some(_) => {},
+            None => { self.measured.insert(key.to_string(), sequence.clone()); }
+        }
+    }
+
+    pub fn add(&mut self, bitstring: String, count: usize) {
+        *self.counts.entry(bitstring).or_insert(0) += count;
+        self.shots += count;
+    }
+
+    // Tallies a shot's loops, numbered the way Shot numbers them
+    pub fn record_iterations(&mut self, iterations: &Vec<(usize, usize)>, gave_up: &Vec<usize>) {
+        for &(number, count) in iterations.iter() {
+            *self.iterations.entry(number).or_default().entry(count).or_insert(0) += 1;
+        }
+
+        for &number in gave_up.iter() {
+            *self.gave_up.entry(number).or_insert(0) += 1;
+        }
+    }
+
+    // Loop number => iterations => how many times the loop went round that often, empty
+    // without loops
+    pub fn iterations(&self) -> &BTreeMap<usize, BTreeMap<usize, usize>> {
+        &self.iterations
+    }
+
+    // Over every time the loop ran, None if it never did
+    pub fn mean_iterations(&self, number: usize) -> Option<f64> {
+        let tally = self.iterations.get(&number)?;
+        let runs: usize = tally.values().sum();
+
+        Some(tally.iter().map(|(&iterations, &count)| iterations * count).sum::<usize>() as f64 / runs as f64)
+    }
+
+    // Loop number => how many times the RepeatUntil loop gave up without meeting its condition
+    pub fn gave_up(&self) -> &BTreeMap<usize, usize> {
+        &self.gave_up
+    }
+
+    pub fn shots(&self) -> usize {
+        self.shots
+    }
+
+    pub fn count(&self, bitstring: &str) -> usize {
+        *self.counts.get(bitstring).unwrap_or(&0)
+    }
+
+    pub fn counts(&self) -> &BTreeMap<String, usize> {
+        &self.counts
+    }
+
+    // The qubit that was measured for each position of the bitstrings, taken from the shots
+    // with the most measurements
+    pub fn qubits(&self) -> &Vec<usize> {
+        &self.qubits
+    }
+
+    // The qubits the shots behind one particular bitstring measured
+    pub fn qubits_of(&self, bitstring: &str) -> Option<&Vec<usize>> {
+        match self.measured.get(bitstring) {
+            Some((qubits, _)) => Some(qubits),
+            None if self.counts.contains_key(bitstring) && bitstring.len() == self.qubits.len() => Some(&self.qubits),
+            None => None
+        }
+    }
+
+    // The label of each position, or the qubit's number for measurements without one
+    pub fn labels(&self) -> Vec<String> {
+        self.qubits.iter().zip(self.labels.iter()).map(|(qubit, label)| {
+            label.clone().unwrap_or_else(|| qubit.to_string())
+        }).collect()
+    }
+
+    // The first position with the given label
+    pub fn position(&self, label: &str) -> Option<usize> {
+        self.labels.iter().position(|l| l.as_deref() == Some(label))
+    }
+
+    pub fn probability(&self, bitstring: &str) -> f64 {
+        if self.shots == 0 { return 0.0 };
+
+        self.count(bitstring) as f64 / self.shots as f64
+    }
+
+    pub fn probabilities(&self) -> BTreeMap<String, f64> {
+        self.counts.keys().map(|key| (key.clone(), self.probability(key))).collect()
+    }
+
+    // Keeps only the measurements at the given positions (in the given order)
+    pub fn marginal(&self, positions: &[usize]) -> Counts {
+        if let Some(p) = positions.iter().find(|&&p| p >= self.qubits.len()) {
+            panic!("position {} is out of range for {} measurements", p, self.qubits.len())
+        };
+
+        let mut result = Counts {
+            qubits: positions.iter().map(|&p| self.qubits[p]).collect(),
+            labels: positions.iter().map(|&p| self.labels[p].clone()).collect(),
+            iterations: self.iterations.clone(),
+            gave_up: self.gave_up.clone(),
+            ..Counts::default()
+        };
+
+        for (key, &count) in self.counts.iter() {
+            let bits: Vec<char> = key.chars().collect();
+
+            if let Some(p) = positions.iter().find(|&&p| p >= bits.len()) {
+                panic!("position {} wasn't measured by the shots that gave {}", p, key)
+            };
+
+            let kept: String = positions.iter().map(|&p| bits[p]).collect();
+
+            if let Some((qubits, labels)) = self.measured.get(key) {
+                result.note(&kept, &(positions.iter().map(|&p| qubits[p]).collect(), positions.iter().map(|&p| labels[p].clone()).collect()));
+            }
+
+            result.add(kept, count);
+        }
+
+        result
+    }
+
+    // The distribution of the remaining measurements over the shots where every
+    // (position, value) pair in the condition held
+    pub fn conditional(&self, condition: &[(usize, bool)]) -> Counts {
+        let remaining: Vec<usize> = (0..self.qubits.len())
+            .filter(|p| condition.iter().all(|(c, _)| c != p))
+            .collect();
+
+        let mut filtered = Counts {
+            qubits: self.qubits.clone(),
+            labels: self.labels.clone(),
+            measured: self.measured.clone(),
+            ..Counts::default()
+        };
+
+        for (key, &count) in self.counts.iter() {
+            let bits: Vec<char> = key.chars().collect();
+
+            if condition.iter().all(|&(p, value)| bits.get(p) == Some(&if value { '1' } else { '0' })) {
+                filtered.add(key.clone(), count);
+            }
+        }
+
+        filtered.marginal(&remaining)
+    }
+
+    // Highest counts first, ties broken by bitstring order
+    pub fn most_frequent(&self, n: usize) -> Vec<(String, usize)> {
+        let mut sorted: Vec<(String, usize)> = self.counts.iter().map(|(key, &count)| (key.clone(), count)).collect();
+
+        sorted.sort_by_key(|entry| std::cmp::Reverse(entry.1));
+        sorted.truncate(n);
+
+        sorted
+    }
+
+    pub fn merge(&mut self, other: &Counts) {
+        if other.qubits.len() > self.qubits.len() {
+            self.qubits = other.qubits.clone();
+            self.labels = other.labels.clone();
+        }
+
+        for (key, sequence) in other.measured.iter() {
+            self.note(key, sequence);
+        }
+
+        for (key, &count) in other.counts.iter() {
+            self.add(key.clone(), count);
+        }
+
+        for (&number, tally) in other.iterations.iter() {
+            for (&iterations, &count) in tally.iter() {
+                *self.iterations.entry(number).or_default().entry(iterations).or_insert(0) += count;
+            }
+        }
+
+        for (&number, &count) in other.gave_up.iter() {
+            *self.gave_up.entry(number).or_insert(0) += count;
+        }
+    }
+
+    pub fn merged(results: Vec<Counts>) -> Counts {
+        let mut accum = Counts::new();
+
+        for result in results.iter() {
+            accum.merge(result);
+        }
+
+        accum
+    }
+}
+
+impl std::fmt::Display for Counts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut lines: Vec<String> = self.counts.keys().map(|key| {
+            let (qubits, labels) = self.measured.get(key).map_or((&self.qubits, &self.labels), |(qubits, labels)| (qubits, labels));
+
+            format!(
+                "Measurements: [ {} ] => {:.2}%",
+                key.chars().zip(qubits.iter().zip(labels.iter())).map(|(bit, (&qubit, label))| {
+                    format!("{:?}", Measurement(qubit, bit == '1', label.clone()))
+                }).collect::<Vec<String>>().join(", "),
+                100.0 * self.probability(key)
+            )
+        }).collect();
+
+        for (number, tally) in self.iterations.iter() {
+            let runs: usize = tally.values().sum();
+            let gave_up = match self.gave_up.get(number) {
+                Some(count) => format!(" gave up {} times", count),
+                None => String::new()
+            };
+
+            lines.push(format!(
+                "Loop {} iterations: [ {} ]{}",
+                number,
+                tally.iter().map(|(iterations, &count)| {
+                    format!("{} => {:.2}%", iterations, 100.0 * count as f64 / runs as f64)
+                }).collect::<Vec<String>>().join(", "),
+                gave_up
+            ));
+        }
+
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+// Cumulative table over an outcome distribution, each draw is a binary search
+pub struct SampleTable {
+    outcomes: Vec<String>,
+    cumulative: Vec<f64>
+}
+
+impl SampleTable {
+    pub fn new(distribution: &BTreeMap<String, f64>) -> Self {
+        let mut total = 0.0;
+
+        let cumulative = distribution.values().map(|probability| {
+            total += probability;
+            total
+        }).collect();
+
+        SampleTable { outcomes: distribution.keys().cloned().collect(), cumulative }
+    }
+
+    pub fn draw<R: Rng>(&self, rng: &mut R) -> usize {
+        let total = *self.cumulative.last().expect("can't sample from an empty distribution");
+        let r = rng.gen::<f64>() * total;
+
+        self.cumulative.partition_point(|&c| c <= r).min(self.cumulative.len() - 1)
+    }
+
+    pub fn sample<R: Rng>(&self, qubits: Vec<usize>, shots: usize, rng: &mut R) -> Counts {
+        self.sample_into(Counts::with_qubits(qubits), shots, rng)
+    }
+
+    // Adds the draws to counts that already know their qubits and labels
+    pub fn sample_into<R: Rng>(&self, mut counts: Counts, shots: usize, rng: &mut R) -> Counts {
+        let mut tally = vec![0; self.outcomes.len()];
+
+        for _ in 0..shots {
+            tally[self.draw(rng)] += 1;
+        }
+
+        for (outcome, &count) in self.outcomes.iter().zip(tally.iter()) {
+            if count > 0 { counts.add(outcome.clone(), count) };
+        }
+
+        counts
+    }
+}
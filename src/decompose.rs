@@ -0,0 +1,111 @@
+use ga_macros::*;
+
+use crate::tensor::*;
+use crate::compare::*;
+
+const IDENTIFY_TOLERANCE: f64 = 1e-9;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EulerBasis {
+    // e^iα Rz(φ) Ry(θ) Rz(λ)
+    ZYZ,
+    // e^iα Rz(φ) Rx(θ) Rz(λ)
+    ZXZ,
+    // e^iα U3(θ, φ, λ)
+    U3
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EulerAngles {
+    pub theta: f64,
+    pub phi: f64,
+    pub lambda: f64,
+    pub phase: f64,
+    pub basis: EulerBasis
+}
+
+impl EulerAngles {
+    // Rebuilds the 2x2 unitary the angles stand for
+    pub fn matrix(&self) -> Vec<Vec<t!()>> {
+        let rotation = match self.basis {
+            EulerBasis::ZYZ => matrix_multiply(&rz(self.phi), &matrix_multiply(&ry(self.theta), &rz(self.lambda))),
+            EulerBasis::ZXZ => matrix_multiply(&rz(self.phi), &matrix_multiply(&rx(self.theta), &rz(self.lambda))),
+            EulerBasis::U3 => u3(self.theta, self.phi, self.lambda)
+        };
+
+        let phase = [self.phase.cos(), self.phase.sin()];
+
+        rotation.iter().map(|row| row.iter().map(|z| eq!(phase * z)).collect()).collect()
+    }
+}
+
+/*
+        Splits any single qubit unitary into three rotations and a global phase. The phase
+    comes from the determinant (e^2iα), which leaves an SU(2) matrix [[a, -b*], [b, a*]] with
+    a = e^-i(φ+λ)/2 cos(θ/2) and b = e^i(φ-λ)/2 sin(θ/2) to read the ZYZ angles off of. The
+    other bases are the same rotations relabeled: Ry(θ) = Rz(π/2) Rx(θ) Rz(-π/2), and
+    U3(θ, φ, λ) = e^i(φ+λ)/2 Rz(φ) Ry(θ) Rz(λ)
+*/
+pub fn euler_angles(gate: &Vec<Vec<t!()>>, basis: EulerBasis) -> EulerAngles {
+    if gate.len() != 2 { panic!("euler angles only exist for single qubit gates, not one of size {}", gate.len()) };
+
+    let (u00, u01, u10, u11) = (gate[0][0], gate[0][1], gate[1][0], gate[1][1]);
+    let determinant = eq!(u00 * u11 - u01 * u10);
+    let phase = determinant[1].atan2(determinant[0]) / 2.0;
+
+    let undo = [phase.cos(), -phase.sin()];
+    let a = eq!(undo * u00);
+    let b = eq!(undo * u10);
+
+    let magnitude = |z: t!()| (z[0].powf(2.0) + z[1].powf(2.0)).sqrt();
+    let (arg_a, arg_b) = (a[1].atan2(a[0]), b[1].atan2(b[0]));
+
+    let theta = 2.0 * magnitude(b).atan2(magnitude(a));
+    let phi = arg_b - arg_a;
+    let lambda = -arg_a - arg_b;
+
+    let half_pi = std::f64::consts::FRAC_PI_2;
+
+    match basis {
+        EulerBasis::ZYZ => EulerAngles { theta, phi, lambda, phase, basis },
+        EulerBasis::ZXZ => EulerAngles { theta, phi: phi + half_pi, lambda: lambda - half_pi, phase, basis },
+        EulerBasis::U3 => EulerAngles { theta, phi, lambda, phase: phase - (phi + lambda) / 2.0, basis }
+    }
+}
+
+// The name of a standard gate the matrix is equal to up to a global phase
+pub fn identify_gate(gate: &Vec<Vec<t!()>>) -> Option<&'static str> {
+    let known: [(&'static str, &Vec<Vec<t!()>>); 13] = [
+        ("I", &IDENTITY),
+        ("H", &HADAMARD),
+        ("X", &PAULIX),
+        ("Y", &PAULIY),
+        ("Z", &PAULIZ),
+        ("S", &SGATE),
+        ("S†", &SDAGGER),
+        ("T", &TGATE),
+        ("T†", &TDAGGER),
+        ("SX", &SXGATE),
+        ("CNOT", &CNOT),
+        ("CZ", &CZ),
+        ("SWAP", &SWAP)
+    ];
+
+    known.iter()
+        .find(|(_, matrix)| matrix.len() == gate.len() && unitary_equiv_up_to_phase(gate, matrix, IDENTIFY_TOLERANCE))
+        .map(|(name, _)| *name)
+}
+
+// What Debug prints for a gate: its name, its U3 angles if it's some other single qubit
+// gate, or the whole matrix
+pub fn describe_gate(gate: &Vec<Vec<t!()>>) -> String {
+    match identify_gate(gate) {
+        Some(name) => name.to_string(),
+        None if gate.len() == 2 => {
+            let angles = euler_angles(gate, EulerBasis::U3);
+
+            format!("U3({:.3}, {:.3}, {:.3})", angles.theta, angles.phi, angles.lambda)
+        },
+        None => print_matrix_without_breaklines(gate)
+    }
+}
@@ -0,0 +1,302 @@
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4};
+
+use ga_macros::*;
+use lazy_static::lazy_static;
+
+use crate::*;
+
+// How close the Weyl coordinates have to be to a special value to use the cheaper circuit
+const WEYL_TOLERANCE: f64 = 1e-9;
+
+lazy_static! {
+    // Local gates are real orthogonal matrices in this basis and XX, YY, ZZ are diagonal
+    static ref MAGIC: Vec<Vec<t!()>> = vec![
+        vec![eq!(1/2^0.5), eq!(0), eq!(0), eq!(i/2^0.5)],
+        vec![eq!(0), eq!(i/2^0.5), eq!(1/2^0.5), eq!(0)],
+        vec![eq!(0), eq!(i/2^0.5), eq!(-1/2^0.5), eq!(0)],
+        vec![eq!(1/2^0.5), eq!(0), eq!(0), eq!(-i/2^0.5)]
+    ];
+
+    static ref PAULI_PAIRS: [Vec<Vec<t!()>>; 3] = [
+        tensor_product_matrix(vec![&PAULIX, &PAULIX]),
+        tensor_product_matrix(vec![&PAULIY, &PAULIY]),
+        tensor_product_matrix(vec![&PAULIZ, &PAULIZ])
+    ];
+}
+
+/*
+        A two qubit gate split into local gates around exp(i(aXX + bYY + cZZ)), with
+    (a, b, c) moved into the Weyl chamber π/4 ≥ a ≥ b ≥ |c|. Gates with the same
+    coordinates only differ by single qubit gates. circuit acts on qubits 0 and 1, uses
+    cnots CNOTs (no more than three) and equals the gate up to a global phase
+*/
+#[derive(Clone, Debug)]
+pub struct TwoQubitDecomposition {
+    pub weyl: [f64; 3],
+    pub cnots: usize,
+    pub circuit: Vec<Instruction>
+}
+
+/*
+        Cartan (KAK) decomposition. In the magic basis the gate U becomes Um, and Umᵀ Um is a
+    symmetric unitary whose real and imaginary parts share a real orthogonal eigenbasis P.
+    That gives Um = O1 K Pᵀ with O1 = Um P K† orthogonal and K = diag(e^iθ) the square root
+    of the eigenvalues. The orthogonal parts are local gates back in the computational
+    basis, and projecting θ onto the diagonals of XX, YY and ZZ gives (a, b, c)
+*/
+pub fn kak_decompose(gate: &Vec<Vec<t!()>>) -> TwoQubitDecomposition {
+    if gate.len() != 4 { panic!("kak_decompose needs a two qubit gate, not one of size {}", gate.len()) };
+
+    let determinant = determinant(gate);
+    let quarter = determinant[1].atan2(determinant[0]) / 4.0;
+    let special = scale(gate, [quarter.cos(), -quarter.sin()]);
+
+    let magic = &*MAGIC;
+    let magic_dagger = dagger(magic);
+    let um = matrix_multiply(&magic_dagger, &matrix_multiply(&special, magic));
+
+    let transposed: Vec<Vec<t!()>> = (0..4).map(|i| (0..4).map(|j| um[j][i]).collect()).collect();
+    let symmetric = matrix_multiply(&transposed, &um);
+
+    let p = orthogonal_eigenbasis(&symmetric);
+    let diagonal = matrix_multiply(&transpose(&p), &matrix_multiply(&symmetric, &p));
+
+    let mut theta: Vec<f64> = (0..4).map(|k| diagonal[k][k][1].atan2(diagonal[k][k][0]) / 2.0).collect();
+
+    // det K has to be 1 for O1 to be a rotation rather than a reflection
+    if theta.iter().sum::<f64>().cos() < 0.0 { theta[0] += std::f64::consts::PI };
+
+    let k_dagger: Vec<Vec<t!()>> = (0..4).map(|i| {
+        (0..4).map(|j| if i == j { [theta[i].cos(), -theta[i].sin()] } else { eq!(0) }).collect()
+    }).collect();
+
+    let o1 = matrix_multiply(&um, &matrix_multiply(&p, &k_dagger));
+    let o2 = transpose(&p);
+
+    let mut left = matrix_multiply(magic, &matrix_multiply(&o1, &magic_dagger));
+    let mut right = matrix_multiply(magic, &matrix_multiply(&o2, &magic_dagger));
+
+    let coordinates: Vec<f64> = PAULI_PAIRS.iter().map(|pair| {
+        let diagonal = matrix_multiply(&magic_dagger, &matrix_multiply(pair, magic));
+
+        (0..4).map(|k| theta[k] * diagonal[k][k][0]).sum::<f64>() / 4.0
+    }).collect();
+
+    let weyl = canonicalize([coordinates[0], coordinates[1], coordinates[2]], &mut left, &mut right);
+
+    let (core, cnots) = interaction_circuit(weyl);
+    let [right_0, right_1] = local_factors(&right);
+    let [left_0, left_1] = local_factors(&left);
+
+    let mut circuit = vec![Instruction::Gate(right_0, 0), Instruction::Gate(right_1, 1)];
+
+    circuit.extend(core);
+    circuit.extend([Instruction::Gate(left_0, 0), Instruction::Gate(left_1, 1)]);
+
+    fuse_gates(&mut circuit);
+
+    TwoQubitDecomposition { weyl, cnots, circuit }
+}
+
+pub fn weyl_coordinates(gate: &Vec<Vec<t!()>>) -> [f64; 3] {
+    kak_decompose(gate).weyl
+}
+
+/*
+        Moves (a, b, c) into the Weyl chamber while keeping exp(i(aXX + bYY + cZZ)) the same
+    up to the local gates collected into left and right. Shifting a coordinate by π/2 splits
+    off ±i PP, conjugating by S⊗S, H⊗H or Rx(π/2)⊗Rx(π/2) swaps two of the coordinates and
+    conjugating by a Pauli on one qubit flips the signs of two of them
+*/
+fn canonicalize(mut weyl: [f64; 3], left: &mut Vec<Vec<t!()>>, right: &mut Vec<Vec<t!()>>) -> [f64; 3] {
+    let shift = |weyl: &mut [f64; 3], k: usize, direction: f64, right: &mut Vec<Vec<t!()>>| {
+        weyl[k] -= direction * FRAC_PI_2;
+        *right = matrix_multiply(&scale(&PAULI_PAIRS[k], [0.0, direction]), right);
+    };
+
+    let conjugate = |local: &Vec<Vec<t!()>>, left: &mut Vec<Vec<t!()>>, right: &mut Vec<Vec<t!()>>| {
+        *left = matrix_multiply(left, &dagger(local));
+        *right = matrix_multiply(local, right);
+    };
+
+    for k in 0..3 {
+        while weyl[k] > FRAC_PI_4 + 1e-12 { shift(&mut weyl, k, 1.0, right) };
+        while weyl[k] < -FRAC_PI_4 + 1e-12 { shift(&mut weyl, k, -1.0, right) };
+    }
+
+    let swaps = [
+        ((0, 1), tensor_product_matrix(vec![&SGATE, &SGATE])),
+        ((0, 2), tensor_product_matrix(vec![&HADAMARD, &HADAMARD])),
+        ((1, 2), tensor_product_matrix(vec![&rx(FRAC_PI_2), &rx(FRAC_PI_2)]))
+    ];
+
+    for ((j, k), local) in [&swaps[0], &swaps[1], &swaps[2]] {
+        if weyl[*j].abs() < weyl[*k].abs() {
+            weyl.swap(*j, *k);
+            conjugate(local, left, right);
+        }
+    }
+
+    // Flipping (a, b) takes Z on the second qubit, (b, c) takes X and (a, c) takes Y
+    let flip = |weyl: &mut [f64; 3], (j, k): (usize, usize), left: &mut Vec<Vec<t!()>>, right: &mut Vec<Vec<t!()>>| {
+        let pauli = match (j, k) { (0, 1) => &*PAULIZ, (1, 2) => &*PAULIX, _ => &*PAULIY };
+
+        weyl[j] = -weyl[j];
+        weyl[k] = -weyl[k];
+        conjugate(&tensor_product_matrix(vec![&IDENTITY, pauli]), left, right);
+    };
+
+    if weyl[0] < 0.0 && weyl[1] < 0.0 {
+        flip(&mut weyl, (0, 1), left, right);
+    } else if weyl[0] < 0.0 {
+        flip(&mut weyl, (0, 2), left, right);
+    } else if weyl[1] < 0.0 {
+        flip(&mut weyl, (1, 2), left, right);
+    }
+
+    // On the a = π/4 face c and -c are the same gate
+    if (weyl[0] - FRAC_PI_4).abs() < WEYL_TOLERANCE && weyl[2] < -WEYL_TOLERANCE {
+        flip(&mut weyl, (0, 2), left, right);
+        shift(&mut weyl, 0, -1.0, right);
+    }
+
+    weyl.map(|x| if x.abs() < 1e-12 { 0.0 } else { x })
+}
+
+/*
+        exp(i(aXX + bYY + cZZ)) (up to phase) with as few CNOTs as the coordinates allow:
+    none for the identity, one for (π/4, 0, 0), two when c = 0 (conjugating
+    CNOT (Rx(-2a) ⊗ Rz(-2b)) CNOT = exp(i(aXX + bZZ)) by Rx(π/2)⊗Rx(π/2) to turn ZZ into YY)
+    and otherwise the three CNOT circuit from Vatan and Williams
+*/
+fn interaction_circuit([a, b, c]: [f64; 3]) -> (Vec<Instruction>, usize) {
+    let zero = |x: f64| x.abs() < WEYL_TOLERANCE;
+    let cnot = || Instruction::Gate(CNOT.clone(), 0);
+    let layer = |q0: Vec<Vec<t!()>>, q1: Vec<Vec<t!()>>| [Instruction::Gate(q0, 0), Instruction::Gate(q1, 1)];
+
+    if zero(a) && zero(b) && zero(c) { return (vec![], 0) };
+
+    if zero(a - FRAC_PI_4) && zero(b) && zero(c) {
+        let mut circuit = vec![Instruction::Gate(HADAMARD.clone(), 0), cnot()];
+
+        circuit.extend(layer(
+            matrix_multiply(&HADAMARD, &rz(-FRAC_PI_2)),
+            matrix_multiply(&HADAMARD, &matrix_multiply(&rz(-FRAC_PI_2), &HADAMARD))
+        ));
+
+        return (circuit, 1);
+    }
+
+    if zero(c) {
+        let r = rx(FRAC_PI_2);
+        let r_dagger = dagger(&r);
+
+        let mut circuit = layer(r_dagger.clone(), r_dagger).to_vec();
+
+        circuit.push(cnot());
+        circuit.extend(layer(rx(-2.0 * a), rz(-2.0 * b)));
+        circuit.push(cnot());
+        circuit.extend(layer(r.clone(), r));
+
+        return (circuit, 2);
+    }
+
+    // The CNOTs pointing from qubit 1 to qubit 0 are CNOT between Hadamards
+    let mut circuit = vec![Instruction::Gate(rz(-FRAC_PI_2), 0)];
+
+    circuit.extend(layer(HADAMARD.clone(), HADAMARD.clone()));
+    circuit.push(cnot());
+    circuit.extend(layer(HADAMARD.clone(), matrix_multiply(&ry(FRAC_PI_2 - 2.0 * b), &HADAMARD)));
+    circuit.push(cnot());
+    circuit.extend(layer(
+        matrix_multiply(&HADAMARD, &rz(FRAC_PI_2 - 2.0 * c)),
+        matrix_multiply(&HADAMARD, &ry(2.0 * a - FRAC_PI_2))
+    ));
+    circuit.push(cnot());
+    circuit.extend(layer(HADAMARD.clone(), matrix_multiply(&rz(FRAC_PI_2), &HADAMARD)));
+
+    (circuit, 3)
+}
+
+// Real orthogonal P with det 1 such that Pᵀ M P is diagonal, for a symmetric unitary M
+fn orthogonal_eigenbasis(matrix: &Vec<Vec<t!()>>) -> Vec<Vec<t!()>> {
+    // The real and imaginary parts commute, the same mixes as unitary_eigen separate them
+    for mix in EIGEN_MIXES {
+        let mixed: Vec<Vec<t!()>> = matrix.iter().map(|row| row.iter().map(|z| [z[0] + mix * z[1], 0.0]).collect()).collect();
+        let (_, vectors) = hermitian_eigen(&mixed);
+
+        let mut p: Vec<Vec<t!()>> = (0..4).map(|i| (0..4).map(|j| [vectors[j][i][0], 0.0]).collect()).collect();
+
+        if determinant(&p)[0] < 0.0 {
+            p.iter_mut().for_each(|row| row[0] = [-row[0][0], 0.0]);
+        }
+
+        let diagonal = matrix_multiply(&transpose(&p), &matrix_multiply(matrix, &p));
+
+        let off_diagonal = (0..4).flat_map(|i| (0..4).map(move |j| (i, j)))
+            .filter(|(i, j)| i != j)
+            .all(|(i, j)| diagonal[i][j][0].abs() < 1e-9 && diagonal[i][j][1].abs() < 1e-9);
+
+        if off_diagonal { return p };
+    }
+
+    panic!("couldn't find a shared eigenbasis for {}", print_matrix(matrix))
+}
+
+// A ⊗ B = local, with B scaled to have determinant 1
+fn local_factors(local: &Vec<Vec<t!()>>) -> [Vec<Vec<t!()>>; 2] {
+    let block = |i: usize, j: usize| -> Vec<Vec<t!()>> {
+        (0..2).map(|k| (0..2).map(|l| local[2 * i + k][2 * j + l]).collect()).collect()
+    };
+
+    let weight = |m: &Vec<Vec<t!()>>| m.iter().flatten().map(|z| z[0].powf(2.0) + z[1].powf(2.0)).sum::<f64>();
+
+    let (i, j) = [(0, 0), (0, 1), (1, 0), (1, 1)].into_iter()
+        .max_by(|&(a, b), &(c, d)| weight(&block(a, b)).partial_cmp(&weight(&block(c, d))).unwrap())
+        .unwrap();
+
+    let largest = block(i, j);
+    let determinant = eq!(largest[0][0] * largest[1][1] - largest[0][1] * largest[1][0]);
+    let (magnitude, angle) = (weight(&vec![vec![determinant]]).powf(0.25), determinant[1].atan2(determinant[0]) / 2.0);
+    let root = [magnitude * angle.cos(), magnitude * angle.sin()];
+
+    let b: Vec<Vec<t!()>> = largest.iter().map(|row| row.iter().map(|z| eq!(z / root)).collect()).collect();
+
+    let a = (0..2).map(|i| {
+        (0..2).map(|j| {
+            let overlap = block(i, j).iter().flatten().zip(b.iter().flatten()).fold(eq!(0), |accum, (z, w)| {
+                let w_ = complement(*w);
+
+                eq!(accum + w_ * z)
+            });
+
+            eq!(overlap / 2)
+        }).collect()
+    }).collect();
+
+    [a, b]
+}
+
+fn scale(matrix: &Vec<Vec<t!()>>, factor: t!()) -> Vec<Vec<t!()>> {
+    matrix.iter().map(|row| row.iter().map(|z| eq!(factor * z)).collect()).collect()
+}
+
+fn transpose(matrix: &Vec<Vec<t!()>>) -> Vec<Vec<t!()>> {
+    (0..matrix[0].len()).map(|j| (0..matrix.len()).map(|i| matrix[i][j]).collect()).collect()
+}
+
+// Laplace expansion along the first row, plenty for 4x4
+fn determinant(matrix: &Vec<Vec<t!()>>) -> t!() {
+    if matrix.len() == 1 { return matrix[0][0] };
+
+    (0..matrix.len()).fold(eq!(0), |accum, column| {
+        let minor: Vec<Vec<t!()>> = matrix[1..].iter().map(|row| {
+            row.iter().enumerate().filter(|&(j, _)| j != column).map(|(_, z)| *z).collect()
+        }).collect();
+
+        let sign = if column % 2 == 0 { 1.0 } else { -1.0 };
+        let (entry, rest) = (matrix[0][column], determinant(&minor));
+
+        eq!(accum + #sign * entry * rest)
+    })
+}
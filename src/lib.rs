@@ -1,12 +1,43 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 use ga_macros::*;
-use rand::Rng;
+use rand::{Rng, SeedableRng, rngs::StdRng};
 // use colored::*;
 
 pub mod tensor;
+pub mod linalg;
+pub mod compare;
+pub mod counts;
+pub mod stats;
+pub mod builder;
+pub mod register;
+pub mod control;
+pub mod optimize;
+pub mod decompose;
+pub mod kak;
+pub mod transpile;
+pub mod routing;
+pub mod synthesis;
+pub mod metrics;
+pub mod schedule;
+mod macros;
 
 pub use tensor::*;
+pub use linalg::*;
+pub use compare::*;
+pub use counts::*;
+pub use stats::*;
+pub use builder::*;
+pub use register::*;
+pub use control::*;
+pub use optimize::*;
+pub use decompose::*;
+pub use kak::*;
+pub use transpile::*;
+pub use routing::*;
+pub use synthesis::*;
+pub use metrics::*;
+pub use schedule::*;
 
 #[derive(Clone)]
 pub enum Instruction {
@@ -15,7 +46,11 @@ pub enum Instruction {
     MeasureAtSpinVector(Vec<t!()>, usize, bool),
     Circuit(Vec<Instruction>, usize),
     Gate(Vec<Vec<t!()>>, usize),
-    Dependent(Box<Instruction>, usize)
+    Dependent(Box<Instruction>, usize),
+    Labeled(String, Box<Instruction>),
+    Repeat(Box<Instruction>, usize),
+    While(Condition, Box<Instruction>, usize),
+    RepeatUntil(Box<Instruction>, Condition)
 }
 
 impl std::fmt::Debug for Instruction {
@@ -24,22 +59,184 @@ impl std::fmt::Debug for Instruction {
             Instruction::Measure(index, _) => write!(f, "{{Measure {} @ |1⟩}}", index),
             Instruction::MeasureAtAngle(angle, index, _) => write!(f, "{{Measure {} @ {:.0}}}", index, angle * 180.0 / std::f64::consts::PI),
             Instruction::MeasureAtSpinVector(spin_vector, index, _) => write!(f, "{{Measure {} @ {}}}", index, print_tensor(spin_vector)),
-            Instruction::Gate(gate, index) => write!(f, "{{Gate: {} @ {}}}", print_matrix_without_breaklines(gate), index),
+            Instruction::Gate(gate, index) => write!(f, "{{Gate: {} @ {}}}", describe_gate(gate), index),
             Instruction::Dependent(circuit, measurement_index) => write!(f, "{{{:?} depending on measurement #{}}}", *circuit, measurement_index),
-            Instruction::Circuit(circuit, index) => write!(f, "{{circuit: {:?} @ {}}}", *circuit, index)
+            Instruction::Circuit(circuit, index) => write!(f, "{{circuit: {:?} @ {}}}", *circuit, index),
+            Instruction::Labeled(label, instruction) => write!(f, "{{{}: {:?}}}", label, *instruction),
+            Instruction::Repeat(instruction, times) => write!(f, "{{{:?} x {}}}", *instruction, times),
+            Instruction::While(condition, instruction, max_iter) => write!(f, "{{while {:?} (at most {}): {:?}}}", condition, max_iter, *instruction),
+            Instruction::RepeatUntil(instruction, condition) => write!(f, "{{{:?} until {:?}}}", *instruction, condition)
         }
     }
 }
 
-fn simplify_instructions(instructions: &mut Vec<Instruction>, measurements: usize, vertical_shift: usize) {
+impl Instruction {
+    // One past the highest qubit the instruction touches
+    pub fn width(&self) -> usize {
+        match self {
+            Instruction::Measure(index, _) => index + 1,
+            Instruction::MeasureAtAngle(_, index, _) => index + 1,
+            Instruction::MeasureAtSpinVector(_, index, _) => index + 1,
+            Instruction::Circuit(circuit, index) => index + circuit.iter().map(|instruction| instruction.width()).max().unwrap_or(0),
+            Instruction::Gate(gate, index) => index + (gate.len() as f64).log2() as usize,
+            Instruction::Dependent(instruction, _) => instruction.width(),
+            Instruction::Labeled(_, instruction) => instruction.width(),
+            Instruction::Repeat(instruction, _) => instruction.width(),
+            Instruction::While(_, instruction, _) | Instruction::RepeatUntil(instruction, _) => instruction.width()
+        }
+    }
+
+    // How many measurements the instruction adds to the numbering Dependent uses. A loop's
+    // measurements only last for one iteration so they don't add any
+    pub fn measurements(&self) -> usize {
+        match self {
+            Instruction::Measure(_, _) | Instruction::MeasureAtAngle(_, _, _) | Instruction::MeasureAtSpinVector(_, _, _) => 1,
+            Instruction::Circuit(circuit, _) => circuit.iter().map(|instruction| instruction.measurements()).sum(),
+            Instruction::Gate(_, _) | Instruction::Dependent(_, _) | Instruction::While(_, _, _) | Instruction::RepeatUntil(_, _) => 0,
+            Instruction::Labeled(_, instruction) => instruction.measurements(),
+            Instruction::Repeat(instruction, times) => times * instruction.measurements()
+        }
+    }
+
+    /*
+            The instruction that undoes this one: gates get their conjugate transpose and
+        circuits run their inverted instructions backwards. Measurements (and anything
+        depending on one) can't be undone, so asking for their inverse panics
+    */
+    pub fn inverse(&self) -> Instruction {
+        match self {
+            Instruction::Gate(gate, index) => Instruction::Gate(dagger(gate), *index),
+            Instruction::Circuit(circuit, index) => Instruction::Circuit(inverse_circuit(circuit), *index),
+            Instruction::Measure(_, _) | Instruction::MeasureAtAngle(_, _, _) | Instruction::MeasureAtSpinVector(_, _, _) => {
+                panic!("can't invert {:?}, measurements aren't reversible", self)
+            },
+            Instruction::Dependent(_, measurement_index) => {
+                panic!("can't invert an instruction depending on measurement #{}, measurements aren't reversible", measurement_index)
+            },
+            Instruction::Labeled(label, _) => panic!("can't invert the measurement labeled {}, measurements aren't reversible", label),
+            Instruction::Repeat(instruction, times) => Instruction::Repeat(Box::new(instruction.inverse()), *times),
+            Instruction::While(_, _, _) | Instruction::RepeatUntil(_, _) => {
+                panic!("can't invert {:?}, loops depend on measurements which aren't reversible", self)
+            }
+        }
+    }
+
+    /*
+            Only carries the instruction out when every control qubit is in its ctrl_state
+        (true for |1⟩, false for |0⟩). The controls are indexed the same way as the
+        instruction's own qubits, and circuits get flattened so each of their gates can be
+        controlled on its own. Measurements can't be controlled coherently so they panic, and
+        so do loops since measurements are what drive them
+    */
+    pub fn controlled(&self, controls: Vec<usize>, ctrl_state: Vec<bool>) -> Instruction {
+        if controls.len() != ctrl_state.len() {
+            panic!("{} controls were given {} control states", controls.len(), ctrl_state.len())
+        };
+
+        match self {
+            Instruction::Gate(gate, index) => {
+                let targets: Vec<usize> = (*index..self.width()).collect();
+
+                if let Some(control) = controls.iter().find(|control| targets.contains(control)) {
+                    panic!("qubit {} can't control a gate acting on it", control)
+                };
+
+                let (matrix, start) = embed_gate(&controlled_gate(gate, &ctrl_state), &[controls, targets].concat());
+
+                Instruction::Gate(matrix, start)
+            },
+            Instruction::Circuit(circuit, index) => Instruction::Circuit(
+                circuit.iter().map(|instruction| instruction.shifted(*index).controlled(controls.clone(), ctrl_state.clone())).collect(),
+                0
+            ),
+            Instruction::Dependent(instruction, measurement_index) => {
+                Instruction::Dependent(Box::new(instruction.controlled(controls, ctrl_state)), *measurement_index)
+            },
+            Instruction::Repeat(instruction, times) => Instruction::Repeat(Box::new(instruction.controlled(controls, ctrl_state)), *times),
+            Instruction::While(_, _, _) | Instruction::RepeatUntil(_, _) => {
+                panic!("can't control {:?}, loops run on measurements which can't be controlled", self)
+            },
+            _ => panic!("can't control {:?}, measurements can't be controlled", self)
+        }
+    }
+
+    fn shifted(&self, by: usize) -> Instruction {
+        match self {
+            Instruction::Measure(index, display) => Instruction::Measure(index + by, *display),
+            Instruction::MeasureAtAngle(angle, index, display) => Instruction::MeasureAtAngle(*angle, index + by, *display),
+            Instruction::MeasureAtSpinVector(spin_vector, index, display) => Instruction::MeasureAtSpinVector(spin_vector.clone(), index + by, *display),
+            Instruction::Circuit(circuit, index) => Instruction::Circuit(circuit.clone(), index + by),
+            Instruction::Gate(gate, index) => Instruction::Gate(gate.clone(), index + by),
+            Instruction::Dependent(instruction, measurement_index) => Instruction::Dependent(Box::new(instruction.shifted(by)), *measurement_index),
+            Instruction::Labeled(label, instruction) => Instruction::Labeled(label.clone(), Box::new(instruction.shifted(by))),
+            Instruction::Repeat(instruction, times) => Instruction::Repeat(Box::new(instruction.shifted(by)), *times),
+            Instruction::While(condition, instruction, max_iter) => Instruction::While(condition.clone(), Box::new(instruction.shifted(by)), *max_iter),
+            Instruction::RepeatUntil(instruction, condition) => Instruction::RepeatUntil(Box::new(instruction.shifted(by)), condition.clone())
+        }
+    }
+
+    /*
+            The instruction raised to the kth power. Gates take any power (fractional ones on
+        the principal branch, see matrix_pow), everything else only whole powers, which
+        become a Repeat of the instruction or of its inverse
+    */
+    pub fn pow(&self, k: f64) -> Instruction {
+        match self {
+            Instruction::Gate(gate, index) => Instruction::Gate(matrix_pow(gate, k), *index),
+            _ if k.fract() != 0.0 => panic!("only single gates have fractional powers, not {:?}", self),
+            _ if k < 0.0 => Instruction::Repeat(Box::new(self.inverse()), -k as usize),
+            _ => Instruction::Repeat(Box::new(self.clone()), k as usize)
+        }
+    }
+
+    // The qubit, display flag and label of a measurement that's been simplified
+    fn measured(&self) -> Option<(usize, bool, Option<&String>)> {
+        match self {
+            Instruction::Measure(index, display) => Some((*index, *display, None)),
+            Instruction::Labeled(label, instruction) => match **instruction {
+                Instruction::Measure(index, display) => Some((index, display, Some(label))),
+                _ => None
+            },
+            _ => None
+        }
+    }
+}
+
+// A conditional or loop body's instructions and where they're placed, the body being part of
+// the circuit around it rather than a sub-circuit with its own measurement numbers
+fn body_circuit(body: Instruction) -> (Vec<Instruction>, usize) {
+    match body {
+        Instruction::Circuit(circuit, index) => (circuit, index),
+        body => (vec![body], 0)
+    }
+}
+
+// Runs the circuit backwards, undoing each instruction
+pub fn inverse_circuit(circuit: &Vec<Instruction>) -> Vec<Instruction> {
+    circuit.iter().rev().map(|instruction| instruction.inverse()).collect()
+}
+
+// The unitary of a measurement free circuit on the given number of qubits
+pub fn circuit_unitary(circuit: &Vec<Instruction>, qubits: usize) -> Vec<Vec<t!()>> {
+    Experiment::new(vec![ZERO.clone(); qubits], circuit.clone()).unitary()
+}
+
+/*
+        measurements is where the circuit's measurement numbers start and counted how many of
+    them came before it. Sub-circuits number their measurements from 0, conditional and loop
+    bodies carry on with the numbers of the circuit around them
+*/
+fn simplify_instructions(instructions: &mut Vec<Instruction>, measurements: usize, counted: usize, vertical_shift: usize) {
     let mut i = 0;
-    let mut new_measurements = 0;
+    let mut new_measurements = counted;
     let mut skip_increment = false;
 
     while i < instructions.len() {
         match instructions.remove(i) {
             Instruction::Circuit(mut circuit, index) => {
-                simplify_instructions(&mut circuit, new_measurements + measurements, vertical_shift + index);
+                simplify_instructions(&mut circuit, new_measurements + measurements, 0, vertical_shift + index);
+
+                new_measurements += circuit.iter().map(|instruction| instruction.measurements()).sum::<usize>();
 
                 let len = circuit.len();
 
@@ -108,9 +305,9 @@ fn simplify_instructions(instructions: &mut Vec<Instruction>, measurements: usiz
                 instructions.insert(i, Instruction::Gate(gate, index + vertical_shift));
             },
             Instruction::Dependent(boxed_instruction, measurement_index) => {
-                let mut circuit = vec![*boxed_instruction];
+                let (mut circuit, index) = body_circuit(*boxed_instruction);
 
-                simplify_instructions(&mut circuit, new_measurements + measurements, vertical_shift);
+                simplify_instructions(&mut circuit, measurements, new_measurements, vertical_shift + index);
 
                 instructions.insert(
                     i,  
@@ -119,6 +316,71 @@ fn simplify_instructions(instructions: &mut Vec<Instruction>, measurements: usiz
                         measurements + measurement_index
                     )
                 );
+            },
+            Instruction::Labeled(label, boxed_instruction) => {
+                let mut circuit = vec![*boxed_instruction];
+
+                simplify_instructions(&mut circuit, new_measurements + measurements, 0, vertical_shift);
+
+                new_measurements += circuit.iter().map(|instruction| instruction.measurements()).sum::<usize>();
+
+                // Only the measurements carry the label, anything the simplification added around them doesn't
+                let len = circuit.len();
+
+                while let Some(instruction) = circuit.pop() {
+                    instructions.insert(i, match instruction {
+                        Instruction::Measure(index, display) => Instruction::Labeled(label.clone(), Box::new(Instruction::Measure(index, display))),
+                        instruction => instruction
+                    });
+                }
+
+                i += len;
+                skip_increment = true;
+            },
+            Instruction::Repeat(boxed_instruction, times) => {
+                if boxed_instruction.measurements() > 0 {
+                    // Every copy needs its own measurement numbers, so they all get written out
+                    for _ in 0..times {
+                        instructions.insert(i, Instruction::Circuit(vec![(*boxed_instruction).clone()], 0));
+                    }
+
+                    skip_increment = true;
+                } else {
+                    let mut circuit = vec![*boxed_instruction];
+
+                    simplify_instructions(&mut circuit, new_measurements + measurements, 0, vertical_shift);
+
+                    // A lone gate gets multiplied out ahead of time, anything bigger is kept once and
+                    // handed to the executor by reference for every repetition
+                    instructions.insert(i, match (circuit.len(), circuit.pop()) {
+                        (1, Some(Instruction::Gate(gate, index))) => Instruction::Gate(matrix_pow(&gate, times as f64), index),
+                        (_, last) => {
+                            circuit.extend(last);
+
+                            Instruction::Repeat(Box::new(Instruction::Circuit(circuit, vertical_shift)), times)
+                        }
+                    });
+                }
+            },
+            Instruction::While(condition, boxed_instruction, max_iter) => {
+                let (mut circuit, index) = body_circuit(*boxed_instruction);
+
+                simplify_instructions(&mut circuit, measurements, new_measurements, vertical_shift + index);
+
+                instructions.insert(
+                    i,
+                    Instruction::While(condition.shifted(measurements), Box::new(Instruction::Circuit(circuit, vertical_shift)), max_iter)
+                );
+            },
+            Instruction::RepeatUntil(boxed_instruction, condition) => {
+                let (mut circuit, index) = body_circuit(*boxed_instruction);
+
+                simplify_instructions(&mut circuit, measurements, new_measurements, vertical_shift + index);
+
+                instructions.insert(
+                    i,
+                    Instruction::RepeatUntil(Box::new(Instruction::Circuit(circuit, vertical_shift)), condition.shifted(measurements))
+                );
             }
         }
 
@@ -127,124 +389,568 @@ fn simplify_instructions(instructions: &mut Vec<Instruction>, measurements: usiz
     }
 }
 
-pub struct Measurement(pub usize, pub bool);
+// The qubit that was measured, the result, and the label it was given (if any)
+#[derive(Clone, PartialEq)]
+pub struct Measurement(pub usize, pub bool, pub Option<String>);
 
 impl std::fmt::Debug for Measurement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}: {}{}", self.0, if self.1 { " " } else { "" }, self.1)
+        match &self.2 {
+            Some(label) => write!(f, "{}={}", label, self.1 as u8),
+            None => write!(f, "{}: {}{}", self.0, if self.1 { " " } else { "" }, self.1)
+        }
     }
 }
 
+/*
+        Everything one run of an experiment produced. iterations holds the number of every loop
+    that ran along with how many times it went round, in the order the loops finished, loops
+    being numbered in the order they appear (each one before the loops in its body). gave_up
+    has the RepeatUntil loops that stopped after MAX_REPEATS tries without meeting their
+    condition
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct Shot {
+    pub state: Vec<t!()>,
+    pub measurements: Vec<Measurement>,
+    pub iterations: Vec<(usize, usize)>,
+    pub gave_up: Vec<usize>
+}
+
+// Shots per rng stream in average_out_with
+const SHOT_CHUNK: usize = 1024;
+
+// How many times RepeatUntil tries before deciding its condition can't be met
+const MAX_REPEATS: usize = 1 << 20;
+
 pub struct Experiment {
-    qbits: Vec<Vec<t!()>>,
+    qubits: usize,
+    state: Vec<t!()>,
     instructions: Vec<Instruction>
 }
 
 impl Experiment {
-    pub fn new(qbits: Vec<Vec<t!()>>, mut instructions: Vec<Instruction>) -> Self {
-        simplify_instructions(&mut instructions, 0, 0);
+    // Starts off in the tensor product of the qubits' states
+    pub fn new(qbits: Vec<Vec<t!()>>, instructions: Vec<Instruction>) -> Self {
+        Experiment::from_state(tensor_product_vector(qbits.iter().collect()), instructions)
+    }
+
+    // Starts off in any normalized state vector, entangled or not
+    pub fn from_state(state: Vec<t!()>, mut instructions: Vec<Instruction>) -> Self {
+        let qubits = (state.len() as f64).log2() as usize;
+
+        if state.len() < 2 || 1 << qubits != state.len() { panic!("a state of length {} isn't made of qubits", state.len()) };
+
+        let length: f64 = state.iter().map(|z| z[0].powf(2.0) + z[1].powf(2.0)).sum();
+
+        if (length - 1.0).abs() > 1e-9 { panic!("the initial state should be normalized, its length squared is {}", length) };
+
+        simplify_instructions(&mut instructions, 0, 0, 0);
 
         // println! ("[\n    {}\n]", instructions.iter().map(|ins| format!("{:?}", ins)).collect::<Vec<String>>().join("\n    "));
 
-        Experiment { qbits, instructions }
+        Experiment { qubits, state, instructions }
+    }
+
+    pub fn qubits(&self) -> usize {
+        self.qubits
+    }
+
+    pub fn initial_state(&self) -> &Vec<t!()> {
+        &self.state
+    }
+
+    // The instructions after simplification, as they'll actually be run
+    pub fn instructions(&self) -> &Vec<Instruction> {
+        &self.instructions
     }
 
-    pub fn average_out(&self, sims: usize) -> Vec<(String, Vec<t!()>, Vec<Measurement>, f64)> {
-        let mut result_map: HashMap<String, (Vec<t!()>, Vec<Measurement>, f64)> = HashMap::new();
+    // Runs fuse_gates over the instructions, returning how many gates it got rid of
+    pub fn optimize(&mut self) -> usize {
+        fuse_gates(&mut self.instructions)
+    }
+
+    // Same as optimize, but lets gates move past the ones they commute with
+    pub fn optimize_commuting(&mut self) -> usize {
+        fuse_commuting_gates(&mut self.instructions)
+    }
+
+    pub fn average_out(&self, sims: usize) -> Counts {
+        self.average_out_with(sims, 1, None)
+    }
+
+    /*
+            Splits the shots into fixed size chunks, each with its own rng stream seeded from
+        the base seed and the chunk number, and hands the chunks out to the worker threads.
+        The per chunk histograms get merged at the end, so a fixed seed gives the same counts
+        no matter how many threads there are
+    */
+    pub fn average_out_with(&self, sims: usize, threads: usize, seed: Option<u64>) -> Counts {
+        let seed = seed.unwrap_or_else(|| rand::thread_rng().gen());
+        let chunks = sims.div_ceil(SHOT_CHUNK);
+        let threads = threads.clamp(1, chunks.max(1));
+
+        let batched = self.batched_instructions().map(|instructions| {
+            (SampleTable::new(&self.distribution(instructions)), self.displayed())
+        });
+
+        let run_chunk = |chunk: usize| {
+            let shots = SHOT_CHUNK.min(sims - chunk * SHOT_CHUNK);
+            let mut rng = StdRng::seed_from_u64(seed.wrapping_add((chunk as u64).wrapping_mul(0x9E3779B97F4A7C15)));
 
-        for _ in 0..sims {
-            let run = self.run();
+            match &batched {
+                Some((table, displayed)) => table.sample_into(displayed.clone(), shots, &mut rng),
+                None => {
+                    let mut counts = Counts::new();
+
+                    for _ in 0..shots {
+                        let shot = self.shot_with(&mut rng);
+
+                        counts.record(&shot.measurements);
+
+                        counts.record_iterations(&shot.iterations, &shot.gave_up);
+                    }
+
+                    counts
+                }
+            }
+        };
+
+        // The workers share the cores, so the kernels inside them don't fan out any further
+        let (budget, threshold) = ((kernel_threads() / threads).max(1), parallel_threshold());
+
+        let results: Vec<Counts> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads).map(|thread| {
+                let run_chunk = &run_chunk;
+
+                scope.spawn(move || with_kernel_threads(budget, || with_parallel_threshold(threshold, || {
+                    Counts::merged((thread..chunks).step_by(threads).map(run_chunk).collect())
+                })))
+            }).collect();
+
+            workers.into_iter().map(|worker| worker.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))).collect()
+        });
+
+        Counts::merged(results)
+    }
 
-            match result_map.get_mut(&format!("{:?}", run.1)) {
-                Some((_, _, num)) => *num += 1.0,
-                None => { result_map.insert(format!("{:?}", run.1), (run.0, run.1, 1.0)); }
-            };
+    /*
+            The 2^n x 2^n matrix the (simplified) circuit carries out, found one column at a
+        time by running each basis state through the gates
+    */
+    pub fn unitary(&self) -> Vec<Vec<t!()>> {
+        let size = 1 << self.qubits;
+
+        let columns: Vec<Vec<t!()>> = (0..size).map(|column| {
+            let mut state = vec![eq!(0); size];
+            state[column] = eq!(1);
+
+            self.evolve(&mut state);
+
+            state
+        }).collect();
+
+        (0..size).map(|row| columns.iter().map(|column| column[row]).collect()).collect()
+    }
+
+    // Runs any state (not just the experiment's own) through the gates. Panics if anything
+    // other than gates is left, since measurements don't have a unitary
+    pub fn evolve(&self, state: &mut Vec<t!()>) {
+        if state.len() != 1 << self.qubits {
+            panic!("a state of length {} doesn't fit {} qubits", state.len(), self.qubits)
+        };
+
+        for instruction in self.unrolled() {
+            match instruction {
+                Instruction::Gate(gate, index) => apply_gate(state, gate, *index),
+                _ => panic!("only measurement free circuits have a unitary, found {:?}", instruction)
+            }
+        }
+    }
+
+    pub fn is_batchable(&self) -> bool {
+        self.batched_instructions().is_some()
+    }
+
+    /*
+            Reorders the instructions so every gate comes before the measurements, which only
+        works when nothing depends on a measurement and no gate mixes measured qubits with
+        unmeasured ones. Gates on unmeasured qubits commute with the measurements before them
+        so they get moved ahead, while gates on measured qubits (like the rotations
+        MeasureAtSpinVector wraps around its measurement) stay behind and are dropped if no
+        later measurement can see them. The gates then only need to be simulated once and
+        every shot can be drawn from the resulting distribution
+    */
+    fn batched_instructions(&self) -> Option<Vec<&Instruction>> {
+        let mut measured = vec![false; self.qubits];
+        let mut gates: Vec<&Instruction> = Vec::new();
+        let mut tail: Vec<&Instruction> = Vec::new();
+
+        for instruction in self.unrolled() {
+            if let Some((index, _, _)) = instruction.measured() {
+                measured[index] = true;
+                tail.push(instruction);
+
+                continue;
+            }
+
+            match instruction {
+                Instruction::Gate(gate, index) => {
+                    let span = (gate.len() as f64).log2() as usize;
+                    let touched = &measured[*index..(*index + span)];
+
+                    if touched.iter().all(|&m| !m) {
+                        gates.push(instruction);
+                    } else if touched.iter().all(|&m| m) {
+                        tail.push(instruction);
+                    } else {
+                        return None;
+                    }
+                },
+                _ => return None
+            }
         }
 
-        result_map.into_iter().map(|(x, (y, z, w))| (x, y, z, w / (sims as f64))).collect()
+        let mut seen = vec![false; self.qubits];
+        let mut kept: Vec<&Instruction> = Vec::new();
+
+        for instruction in tail.into_iter().rev() {
+            if let Some((index, _, _)) = instruction.measured() {
+                seen[index] = true;
+                kept.push(instruction);
+
+                continue;
+            }
+
+            if let Instruction::Gate(gate, index) = instruction {
+                let span = (gate.len() as f64).log2() as usize;
+
+                if seen[*index..(*index + span)].iter().any(|&s| s) {
+                    seen[*index..(*index + span)].iter_mut().for_each(|s| *s = true);
+                    kept.push(instruction);
+                }
+            }
+        }
+
+        gates.extend(kept.into_iter().rev());
+
+        Some(gates)
+    }
+
+    // The instructions with every repeated block written out by reference
+    fn unrolled(&self) -> Vec<&Instruction> {
+        fn unroll<'a>(instruction: &'a Instruction, into: &mut Vec<&'a Instruction>) {
+            match instruction {
+                Instruction::Repeat(body, times) => (0..*times).for_each(|_| unroll(body, into)),
+                Instruction::Circuit(circuit, _) => circuit.iter().for_each(|instruction| unroll(instruction, into)),
+                _ => into.push(instruction)
+            }
+        }
+
+        let mut unrolled = Vec::new();
+
+        self.instructions.iter().for_each(|instruction| unroll(instruction, &mut unrolled));
+
+        unrolled
+    }
+
+    // Empty counts set up with the qubit and label behind each displayed measurement, in
+    // order. Only meaningful when no measurement is conditional
+    fn displayed(&self) -> Counts {
+        let (qubits, labels) = self.instructions.iter().filter_map(|instruction| match instruction.measured() {
+            Some((index, true, label)) => Some((index, label.cloned())),
+            _ => None
+        }).unzip();
+
+        Counts::with_labels(qubits, labels)
     }
 
     pub fn average_out_pretty(&self, sims: usize) -> String {
-        self
-            .average_out(sims)
-            .iter()
-            .map(|(_, _, measurements, num)| {
-                format!(
-                    "Measurements: [ {} ] => {:.2}%", 
-                    measurements.iter().map(|m| format!("{:?}", m)).collect::<Vec<String>>().join(", "),
-                    100.0 * num
-                )
-            })
-            .collect::<Vec<String>>()
-            .join("\n")
+        self.average_out(sims).to_string()
     }
     
     pub fn run(&self) -> (Vec<t!()>, Vec<Measurement>) {
-        let mut measurements: Vec<(bool, Measurement)> = Vec::new();
-        let mut qbits_tensor = tensor_product_vector(self.qbits.iter().collect());
-        let mut instructions: Vec<&Instruction> = self.instructions.iter().collect();
+        self.run_with(&mut rand::thread_rng())
+    }
+
+    pub fn run_with<R: Rng>(&self, rng: &mut R) -> (Vec<t!()>, Vec<Measurement>) {
+        let shot = self.shot_with(rng);
+
+        (shot.state, shot.measurements)
+    }
+
+    pub fn shot(&self) -> Shot {
+        self.shot_with(&mut rand::thread_rng())
+    }
+
+    pub fn shot_with<R: Rng>(&self, rng: &mut R) -> Shot {
+        let mut shot = Shot { state: self.state.clone(), measurements: Vec::new(), iterations: Vec::new(), gave_up: Vec::new() };
+
+        self.execute(self.instructions.iter().collect(), &mut shot, &mut Vec::new(), rng);
+
+        shot
+    }
+
+    /*
+            The displayed measurements go straight into the shot, measurements holds every one
+        of them that can still be looked up by number
+    */
+    fn execute<R: Rng>(
+        &self,
+        mut instructions: Vec<&Instruction>,
+        shot: &mut Shot,
+        measurements: &mut Vec<(bool, Measurement)>,
+        rng: &mut R
+    ) {
         let mut i = 0;
-    
+
         while i < instructions.len() {
-            match instructions[i] {
-                Instruction::Measure(index, display) => {
+            match (instructions[i], instructions[i].measured()) {
+                (_, Some((index, display, label))) => {
                     let measurement = choose_probability(
-                        &qbits_tensor.iter().map(|z| z[0].powf(2.0) + z[1].powf(2.0)).collect()
+                        &shot.state.iter().map(|z| z[0].powf(2.0) + z[1].powf(2.0)).collect(),
+                        rng
                     ).expect("choose_probability couldn't choose a probability");
 
-                    let result = is_from_one(*index, measurement, qbits_tensor.len());
+                    let result = is_from_one(index, measurement, shot.state.len());
 
-                    for i in 0..qbits_tensor.len() {
-                        if is_from_one(*index, i, qbits_tensor.len()) != result {
-                            qbits_tensor[i] = eq!(0);
-                        }
-                    }
+                    collapse(&mut shot.state, index, result);
 
-                    measurements.push((*display, Measurement(*index, result)));
+                    let measurement = Measurement(index, result, label.cloned());
 
-                    norm(&mut qbits_tensor);
+                    if display { shot.measurements.push(measurement.clone()) };
+
+                    measurements.push((display, measurement));
                 },
-                Instruction::MeasureAtAngle(_, _, _) => {}, // Should have been removed
-                Instruction::MeasureAtSpinVector(_, _, _) => {}, // Should have been removed
-                Instruction::Circuit(circuit, _) => { // Should only activate after dependent
-                    for instruction in circuit.iter() {
-                        instructions.insert(i + 1, instruction);
+                /*
+                        Each iteration's measurements get dropped from the numbering once the
+                    condition has seen them, so the next iteration (and everything after the
+                    loop) finds the measurement numbers where they were when the loop started.
+                    Displayed ones stay in the shot
+                */
+                (Instruction::While(condition, body, max_iter), _) => {
+                    let start = measurements.len();
+                    let mut count = 0;
+
+                    while count < *max_iter && condition.holds(&results(measurements)) {
+                        measurements.truncate(start);
+                        self.execute(vec![body], shot, measurements, rng);
+
+                        count += 1;
                     }
+
+                    measurements.truncate(start);
+                    shot.iterations.push((self.loop_number(instructions[i]), count));
                 },
-                Instruction::Gate(gate, index) => {
-                    let mut matrices: Vec<&Vec<Vec<t!()>>> = vec![&*IDENTITY; self.qbits.len()];
+                (Instruction::RepeatUntil(body, condition), _) => {
+                    let start = measurements.len();
+                    let number = self.loop_number(instructions[i]);
+                    let mut count = 0;
 
-                    matrices[*index] = &gate; 
+                    loop {
+                        measurements.truncate(start);
+                        self.execute(vec![body], shot, measurements, rng);
 
-                    let excess_amount = (gate.len() as f64).log2() as usize - 1;
+                        count += 1;
 
-                    for _ in 0..excess_amount { matrices.remove(*index + 1); }
+                        if condition.holds(&results(measurements)) { break };
 
-                    pass_gate(&mut qbits_tensor, &tensor_product_matrix(matrices));
-                },
-                Instruction::Dependent(instruction, measurement_index) => {
-                    if measurements
-                        .get(*measurement_index)
-                        .expect(format!(
-                            "measurement_index: {} should not be greater than the size of measurements: {:?}", 
-                            *measurement_index, 
-                            measurements
-                        ).as_str()).1.1
-                    {
-                        instructions.insert(i + 1, &*instruction)
+                        if count >= MAX_REPEATS {
+                            shot.gave_up.push(number);
+
+                            break;
+                        }
                     }
-                }
+
+                    measurements.truncate(start);
+                    shot.iterations.push((number, count));
+                },
+                _ => self.apply(&mut instructions, i, &mut shot.state, measurements)
             }
 
             i += 1;
         };
-    
-        (qbits_tensor, measurements.into_iter().filter(|m| m.0 == true).map(|(_, m)| m).collect())
     }
+
+    // Where the loop comes in the order Shot numbers loops in
+    fn loop_number(&self, target: &Instruction) -> usize {
+        let mut loops = Vec::new();
+
+        self.instructions.iter().for_each(|instruction| collect_loops(instruction, &mut loops));
+
+        loops.iter().position(|&instruction| std::ptr::eq(instruction, target)).expect("the loop isn't part of the experiment")
+    }
+
+    // Every possible set of displayed measurement results along with its exact probability,
+    // found by following both outcomes of every measurement
+    pub fn exact_distribution(&self) -> BTreeMap<String, f64> {
+        self.distribution(self.instructions.iter().collect())
+    }
+
+    fn distribution(&self, instructions: Vec<&Instruction>) -> BTreeMap<String, f64> {
+        let mut distribution: BTreeMap<String, f64> = BTreeMap::new();
+        let mut branches: Vec<Branch> = vec![Branch {
+            instructions,
+            i: 0,
+            qbits_tensor: self.state.clone(),
+            measurements: Vec::new(),
+            probability: 1.0
+        }];
+
+        'branches: while let Some(mut branch) = branches.pop() {
+            while branch.i < branch.instructions.len() {
+                // Only measurements are left, so the outcomes can be read straight off the amplitudes
+                if branch.instructions[branch.i..].iter().all(|instruction| instruction.measured().is_some()) {
+                    let len = branch.qbits_tensor.len();
+
+                    for k in 0..len {
+                        let probability = branch.qbits_tensor[k][0].powf(2.0) + branch.qbits_tensor[k][1].powf(2.0);
+
+                        if probability < 1e-12 { continue };
+
+                        let displayed: Vec<Measurement> = branch.measurements
+                            .iter()
+                            .filter(|m| m.0)
+                            .map(|(_, m)| m.clone())
+                            .chain(branch.instructions[branch.i..].iter().filter_map(|instruction| match instruction.measured() {
+                                Some((index, true, label)) => Some(Measurement(index, is_from_one(index, k, len), label.cloned())),
+                                _ => None
+                            }))
+                            .collect();
+
+                        *distribution.entry(bitstring(&displayed)).or_insert(0.0) += branch.probability * probability;
+                    }
+
+                    continue 'branches;
+                }
+
+                if let Some((index, display, label)) = branch.instructions[branch.i].measured() {
+                    let one = probability_of_one(&branch.qbits_tensor, index);
+
+                    for (result, probability) in [(false, 1.0 - one), (true, one)] {
+                        if probability < 1e-12 { continue };
+
+                        let mut next = Branch {
+                            instructions: branch.instructions.clone(),
+                            i: branch.i + 1,
+                            qbits_tensor: branch.qbits_tensor.clone(),
+                            measurements: branch.measurements.clone(),
+                            probability: branch.probability * probability
+                        };
+
+                        collapse(&mut next.qbits_tensor, index, result);
+                        next.measurements.push((display, Measurement(index, result, label.cloned())));
+
+                        branches.push(next);
+                    }
+
+                    break;
+                }
+
+                self.apply(&mut branch.instructions, branch.i, &mut branch.qbits_tensor, &branch.measurements);
+
+                branch.i += 1;
+            }
+
+            if branch.i >= branch.instructions.len() {
+                let displayed: Vec<Measurement> = branch.measurements.into_iter().filter(|m| m.0).map(|(_, m)| m).collect();
+
+                *distribution.entry(bitstring(&displayed)).or_insert(0.0) += branch.probability;
+            }
+        }
+
+        distribution
+    }
+
+    // Carries out every instruction other than a measurement
+    fn apply(
+        &self, 
+        instructions: &mut Vec<&Instruction>, 
+        i: usize, 
+        qbits_tensor: &mut Vec<t!()>, 
+        measurements: &Vec<(bool, Measurement)>
+    ) {
+        match instructions[i] {
+            Instruction::Measure(_, _) | Instruction::Labeled(_, _) => panic!("measurements can't be applied deterministically"),
+            Instruction::MeasureAtAngle(_, _, _) => {}, // Should have been removed
+            Instruction::MeasureAtSpinVector(_, _, _) => {}, // Should have been removed
+            Instruction::Circuit(circuit, _) => { // Should only activate after dependent or repeat
+                // Each one goes in right after the circuit, so going backwards keeps them in order
+                for instruction in circuit.iter().rev() {
+                    instructions.insert(i + 1, instruction);
+                }
+            },
+            Instruction::Repeat(instruction, times) => {
+                for _ in 0..*times {
+                    instructions.insert(i + 1, instruction);
+                }
+            },
+            Instruction::While(_, _, _) | Instruction::RepeatUntil(_, _) => {
+                panic!("loops can only be run shot by shot, their outcomes can't all be followed")
+            },
+            Instruction::Gate(gate, index) => apply_gate(qbits_tensor, gate, *index),
+            Instruction::Dependent(instruction, measurement_index) => {
+                if measurements
+                    .get(*measurement_index)
+                    .unwrap_or_else(|| panic!(
+                        "measurement_index: {} should not be greater than the size of measurements: {:?}", 
+                        *measurement_index, 
+                        measurements
+                    )).1.1
+                {
+                    instructions.insert(i + 1, instruction)
+                }
+            }
+        }
+    }
+}
+
+struct Branch<'a> {
+    instructions: Vec<&'a Instruction>,
+    i: usize,
+    qbits_tensor: Vec<t!()>,
+    measurements: Vec<(bool, Measurement)>,
+    probability: f64
+}
+
+fn collect_loops<'a>(instruction: &'a Instruction, into: &mut Vec<&'a Instruction>) {
+    match instruction {
+        Instruction::Circuit(circuit, _) => circuit.iter().for_each(|instruction| collect_loops(instruction, into)),
+        Instruction::Dependent(body, _) | Instruction::Repeat(body, _) => collect_loops(body, into),
+        Instruction::While(_, body, _) | Instruction::RepeatUntil(body, _) => {
+            into.push(instruction);
+            collect_loops(body, into);
+        },
+        _ => {}
+    }
+}
+
+fn results(measurements: &Vec<(bool, Measurement)>) -> Vec<bool> {
+    measurements.iter().map(|(_, m)| m.1).collect()
+}
+
+fn probability_of_one(tensor: &Vec<t!()>, index: usize) -> f64 {
+    (0..tensor.len())
+        .filter(|&i| is_from_one(index, i, tensor.len()))
+        .map(|i| tensor[i][0].powf(2.0) + tensor[i][1].powf(2.0))
+        .sum()
+}
+
+fn collapse(tensor: &mut Vec<t!()>, index: usize, result: bool) {
+    let len = tensor.len();
+
+    for_each_block(tensor, |offset, block| {
+        for (k, z) in block.iter_mut().enumerate() {
+            if is_from_one(index, offset + k, len) != result {
+                *z = eq!(0);
+            }
+        }
+    });
+
+    norm(tensor);
 }
 
-fn choose_probability(probabilities: &Vec<f64>) -> Option<usize> {
-    let mut rng = rand::thread_rng();
+fn choose_probability<R: Rng>(probabilities: &Vec<f64>, rng: &mut R) -> Option<usize> {
     let mut r = rng.gen::<f64>();
 
     for i in 0..probabilities.len() {
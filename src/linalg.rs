@@ -0,0 +1,165 @@
+use ga_macros::*;
+
+use crate::tensor::*;
+
+const JACOBI_SWEEPS: usize = 100;
+
+/*
+        Cyclic Jacobi eigenvalue algorithm for hermitian matrices. Every rotation zeroes one
+    off diagonal pair after first rotating its phase away, so the real rotation from the
+    symmetric case can be reused. Returns the eigenvalues in ascending order along with
+    the matching (normalized) eigenvectors.
+*/
+pub fn hermitian_eigen(matrix: &Vec<Vec<t!()>>) -> (Vec<f64>, Vec<Vec<t!()>>) {
+    let n = matrix.len();
+    let mut a = matrix.clone();
+    let mut v = identity(n);
+
+    for _ in 0..JACOBI_SWEEPS {
+        let mut off_diagonal = 0.0;
+
+        for p in 0..n {
+            for q in (p + 1)..n {
+                off_diagonal += a[p][q][0].powf(2.0) + a[p][q][1].powf(2.0);
+            }
+        }
+
+        if off_diagonal < 1e-30 { break };
+
+        for p in 0..n {
+            for q in (p + 1)..n {
+                let magnitude = (a[p][q][0].powf(2.0) + a[p][q][1].powf(2.0)).powf(0.5);
+
+                if magnitude < 1e-300 { continue };
+
+                let e = [a[p][q][0] / magnitude, a[p][q][1] / magnitude];
+                let e_ = complement(e);
+
+                let tau = (a[q][q][0] - a[p][p][0]) / (2.0 * magnitude);
+                let sign = if tau >= 0.0 { 1.0 } else { -1.0 };
+                let t = sign / (tau.abs() + (1.0 + tau * tau).powf(0.5));
+                let c = 1.0 / (1.0 + t * t).powf(0.5);
+                let s = t * c;
+
+                // Columns: A ← A G with G = [[c, s], [-s e_, c e_]]
+                for k in 0..n {
+                    let (akp, akq) = (a[k][p], a[k][q]);
+
+                    a[k][p] = eq!(#c * akp - #s * e_ * akq);
+                    a[k][q] = eq!(#s * akp + #c * e_ * akq);
+
+                    let (vkp, vkq) = (v[k][p], v[k][q]);
+
+                    v[k][p] = eq!(#c * vkp - #s * e_ * vkq);
+                    v[k][q] = eq!(#s * vkp + #c * e_ * vkq);
+                }
+
+                // Rows: A ← G† A
+                for k in 0..n {
+                    let (apk, aqk) = (a[p][k], a[q][k]);
+
+                    a[p][k] = eq!(#c * apk - #s * e * aqk);
+                    a[q][k] = eq!(#s * apk + #c * e * aqk);
+                }
+            }
+        }
+    }
+
+    let mut order: Vec<usize> = (0..n).collect();
+
+    order.sort_by(|&i, &j| a[i][i][0].partial_cmp(&a[j][j][0]).unwrap());
+
+    (
+        order.iter().map(|&i| a[i][i][0]).collect(),
+        order.iter().map(|&i| (0..n).map(|k| v[k][i]).collect()).collect()
+    )
+}
+
+// Mixes of the hermitian and anti-hermitian parts tried in turn, anything irrational-ish keeps
+// the eigenvalues of the two parts from cancelling into a false degeneracy
+pub(crate) const EIGEN_MIXES: [f64; 4] = [0.577215, 1.324718, -0.412781, 3.359886];
+
+// How far off the diagonal P†UP can be for P to count as U's eigenbasis
+const EIGEN_TOLERANCE: f64 = 1e-10;
+
+/*
+        Eigenvalues and eigenvectors (as the columns of a unitary P) of a unitary U. U splits
+    into its commuting hermitian parts (U + U†)/2 and (U - U†)/2i, and a mix of the two gets
+    diagonalized with hermitian_eigen. An unlucky mix can make two different eigenvalues of U
+    look the same, so P is checked by diagonalizing U with it and the next mix is tried if it
+    doesn't
+*/
+pub fn unitary_eigen(matrix: &Vec<Vec<t!()>>) -> (Vec<t!()>, Vec<Vec<t!()>>) {
+    let n = matrix.len();
+    let adjoint = dagger(matrix);
+
+    for mix in EIGEN_MIXES {
+        let hermitian: Vec<Vec<t!()>> = (0..n).map(|row| {
+            (0..n).map(|column| {
+                let (u, u_) = (matrix[row][column], adjoint[row][column]);
+
+                eq!((u + u_) / 2 - #mix * i * (u - u_) / 2)
+            }).collect()
+        }).collect();
+
+        let (_, vectors) = hermitian_eigen(&hermitian);
+        let p: Vec<Vec<t!()>> = (0..n).map(|row| (0..n).map(|column| vectors[column][row]).collect()).collect();
+        let diagonal = matrix_multiply(&dagger(&p), &matrix_multiply(matrix, &p));
+
+        let separated = (0..n).all(|row| (0..n).all(|column| {
+            row == column || (diagonal[row][column][0].abs() < EIGEN_TOLERANCE && diagonal[row][column][1].abs() < EIGEN_TOLERANCE)
+        }));
+
+        if separated { return ((0..n).map(|k| diagonal[k][k]).collect(), p) };
+    }
+
+    panic!("couldn't diagonalize {}", print_matrix(matrix))
+}
+
+/*
+        U^k for a unitary U. Whole powers are done by repeated squaring (negative ones on
+    U†). Fractional powers take the principal branch: U gets diagonalized with unitary_eigen
+    and each eigenvalue e^iφ (with φ in (-π, π]) becomes e^ikφ
+*/
+pub fn matrix_pow(matrix: &Vec<Vec<t!()>>, k: f64) -> Vec<Vec<t!()>> {
+    let n = matrix.len();
+
+    if k.fract() == 0.0 {
+        let mut base = if k < 0.0 { dagger(matrix) } else { matrix.clone() };
+        let mut result = identity(n);
+        let mut exponent = k.abs() as u64;
+
+        while exponent > 0 {
+            if exponent & 1 == 1 { result = matrix_multiply(&result, &base) };
+
+            exponent >>= 1;
+
+            if exponent > 0 { base = matrix_multiply(&base, &base) };
+        }
+
+        return result;
+    }
+
+    let (values, p) = unitary_eigen(matrix);
+    let mut result = vec![vec![eq!(0); n]; n];
+
+    for (index, lambda) in values.iter().enumerate() {
+        let mut angle = lambda[1].atan2(lambda[0]);
+
+        // -π and π are the same eigenvalue, rounding shouldn't decide which branch it lands on
+        if angle < -std::f64::consts::PI + 1e-9 { angle = std::f64::consts::PI };
+
+        let power = [(k * angle).cos(), (k * angle).sin()];
+
+        for row in 0..n {
+            for column in 0..n {
+                let (a, b_) = (p[row][index], complement(p[column][index]));
+                let z = result[row][column];
+
+                result[row][column] = eq!(z + power * a * b_);
+            }
+        }
+    }
+
+    result
+}
@@ -0,0 +1,153 @@
+/*
+        QASM-like shorthand for CircuitBuilder that expands into the builder calls at compile
+    time and gives back the Vec<Instruction>:
+
+        circuit! {
+            qubits 3;
+            h 0;
+            cx 0 1;
+            rz(std::f64::consts::PI / 4.0) 2;
+            gate(SWAP) 2, 0;
+            m0 = measure 1;
+            if m0 { x 2 }
+            sub(TELEPORT) 0;
+            inverse(ENTANGLE) 1;
+            repeat(GROVER_ITERATION, 2) 0;
+        }
+
+    `m = measure q;` binds m to the measurement's number for later `if` blocks, and
+    `measure_hidden` keeps the result out of the output. Unknown gates and statements that
+    don't fit any of the forms are compile errors
+*/
+/// ```compile_fail
+/// // There's no gate called hadamard
+/// let circuit = quantum_sim::circuit! { qubits 1; hadamard 0; };
+/// ```
+///
+/// ```compile_fail
+/// // A gate without its qubit
+/// let circuit = quantum_sim::circuit! { qubits 1; h; };
+/// ```
+#[macro_export]
+macro_rules! circuit {
+    (qubits $n:tt; $($rest:tt)*) => {{
+        #[allow(unused_mut)]
+        let mut __circuit = $crate::CircuitBuilder::new($n);
+
+        $crate::circuit!(@stmts __circuit; $($rest)*);
+
+        __circuit.build()
+    }};
+
+    (@stmts $b:ident;) => {};
+
+    (@stmts $b:ident; ; $($rest:tt)*) => {
+        $crate::circuit!(@stmts $b; $($rest)*);
+    };
+
+    (@stmts $b:ident; if $m:ident { $($body:tt)* } $($rest:tt)*) => {
+        $b = $b.when($m, |__body| {
+            let mut __body = __body;
+
+            $crate::circuit!(@stmts __body; $($body)* ;);
+
+            __body
+        });
+
+        $crate::circuit!(@stmts $b; $($rest)*);
+    };
+
+    (@stmts $b:ident; $name:ident = $kind:ident $q:tt; $($rest:tt)*) => {
+        $crate::circuit!(@check_measure $kind);
+
+        let $name = $b.measurements();
+        $b = $b.$kind($q);
+
+        $crate::circuit!(@stmts $b; $($rest)*);
+    };
+
+    (@stmts $b:ident; sub($c:expr) $offset:tt; $($rest:tt)*) => {
+        $b = $b.sub(&$c, $offset);
+
+        $crate::circuit!(@stmts $b; $($rest)*);
+    };
+
+    (@stmts $b:ident; inverse($c:expr) $offset:tt; $($rest:tt)*) => {
+        $b = $b.sub_inverse(&$c, $offset);
+
+        $crate::circuit!(@stmts $b; $($rest)*);
+    };
+
+    (@stmts $b:ident; repeat($c:expr, $times:expr) $offset:tt; $($rest:tt)*) => {
+        $b = $b.repeat(&$c, $offset, $times);
+
+        $crate::circuit!(@stmts $b; $($rest)*);
+    };
+
+    (@stmts $b:ident; gate($g:expr) $($q:expr),+; $($rest:tt)*) => {
+        $b = $b.gate(&$g, &[$($q),+]);
+
+        $crate::circuit!(@stmts $b; $($rest)*);
+    };
+
+    (@stmts $b:ident; measure_at($angle:expr) $q:tt; $($rest:tt)*) => {
+        $b = $b.measure_at_angle($angle, $q);
+
+        $crate::circuit!(@stmts $b; $($rest)*);
+    };
+
+    (@stmts $b:ident; $gate:ident($theta:expr) $q:tt; $($rest:tt)*) => {
+        $crate::circuit!(@check_rotation $gate);
+
+        $b = $b.$gate($theta, $q);
+
+        $crate::circuit!(@stmts $b; $($rest)*);
+    };
+
+    (@stmts $b:ident; $gate:ident $q:tt; $($rest:tt)*) => {
+        $crate::circuit!(@check_one $gate);
+
+        $b = $b.$gate($q);
+
+        $crate::circuit!(@stmts $b; $($rest)*);
+    };
+
+    (@stmts $b:ident; $gate:ident $q1:tt $q2:tt; $($rest:tt)*) => {
+        $crate::circuit!(@check_two $gate);
+
+        $b = $b.$gate($q1, $q2);
+
+        $crate::circuit!(@stmts $b; $($rest)*);
+    };
+
+    (@stmts $b:ident; $($bad:tt)*) => {
+        compile_error!(concat!("malformed circuit statement: ", stringify!($($bad)*)));
+    };
+
+    (@check_one h) => {}; (@check_one x) => {}; (@check_one y) => {}; (@check_one z) => {};
+    (@check_one s) => {}; (@check_one sdg) => {}; (@check_one t) => {}; (@check_one tdg) => {};
+    (@check_one sx) => {};
+    (@check_one measure) => {}; (@check_one measure_hidden) => {};
+    (@check_one $other:ident) => {
+        compile_error!(concat!("unknown single qubit gate `", stringify!($other), "`"));
+    };
+
+    (@check_two cx) => {}; (@check_two cz) => {}; (@check_two swap) => {};
+    (@check_two $other:ident) => {
+        compile_error!(concat!("unknown two qubit gate `", stringify!($other), "`"));
+    };
+
+    (@check_rotation rx) => {}; (@check_rotation ry) => {}; (@check_rotation rz) => {}; (@check_rotation phase) => {};
+    (@check_rotation $other:ident) => {
+        compile_error!(concat!("unknown rotation gate `", stringify!($other), "`"));
+    };
+
+    (@check_measure measure) => {}; (@check_measure measure_hidden) => {};
+    (@check_measure $other:ident) => {
+        compile_error!(concat!("only measurements can be named, not `", stringify!($other), "`"));
+    };
+
+    ($($rest:tt)*) => {
+        compile_error!("circuit! has to start with `qubits <count>;`");
+    };
+}
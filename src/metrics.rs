@@ -0,0 +1,140 @@
+use std::collections::BTreeMap;
+
+use ga_macros::*;
+
+use crate::*;
+
+/*
+        Counts and timing of an experiment's simplified instructions. Gates are counted by the
+    qubits they really act on, so a CNOT spread over distant qubits is still a two qubit CNOT,
+    and named after the standard gate they match (in either qubit order). Every gate and
+    measurement takes one moment, the depth being the number of moments in an ASAP schedule,
+    so conditional gates wait for the measurement they depend on as well as their qubits.
+    Repeated blocks count every repetition, loops count the gates of one iteration but take
+    up a single moment
+*/
+#[derive(Clone, Debug)]
+pub struct Metrics {
+    pub gates: usize,
+    pub gate_counts: BTreeMap<String, usize>,
+    pub two_qubit_gates: usize,
+    pub t_count: usize,
+    pub measurements: usize,
+    pub depth: usize,
+    // The moments up to and including each qubit's last one, 0 for untouched qubits
+    pub qubit_depths: Vec<usize>,
+    // A longest chain of instructions, each one waiting on the one before
+    pub critical_path: Vec<Instruction>
+}
+
+impl Metrics {
+    pub fn count(&self, kind: &str) -> usize {
+        *self.gate_counts.get(kind).unwrap_or(&0)
+    }
+}
+
+impl Experiment {
+    pub fn metrics(&self) -> Metrics {
+        let mut tally = Tally { gate_counts: BTreeMap::new(), two_qubit_gates: 0, measurements: 0 };
+
+        tally.walk(&self.instructions);
+
+        let dependencies = dependencies(&self.instructions, self.qubits);
+        let levels = asap(&dependencies);
+        let mut qubit_depths = vec![0; self.qubits];
+
+        for (qubits, level) in dependencies.qubits.iter().zip(levels.iter()) {
+            qubits.iter().for_each(|&q| qubit_depths[q] = qubit_depths[q].max(level + 1));
+        }
+
+        let count = |kind: &str| *tally.gate_counts.get(kind).unwrap_or(&0);
+
+        Metrics {
+            gates: tally.gate_counts.values().sum(),
+            two_qubit_gates: tally.two_qubit_gates,
+            t_count: count("T") + count("T†"),
+            measurements: tally.measurements,
+            depth: levels.iter().max().map_or(0, |level| level + 1),
+            qubit_depths,
+            critical_path: critical_path(&dependencies, &levels),
+            gate_counts: tally.gate_counts
+        }
+    }
+}
+
+/*
+        Back from the last instruction of the deepest moment, each time to an instruction that
+    put it in its moment: one in the moment before if there is one, otherwise one it can share
+    the moment with
+*/
+fn critical_path(dependencies: &Dependencies, levels: &Vec<usize>) -> Vec<Instruction> {
+    let mut path = Vec::new();
+    let deepest = levels.iter().max();
+    let mut step = (0..levels.len()).rev().find(|&i| Some(&levels[i]) == deepest);
+
+    while let Some(i) = step {
+        path.push(dependencies.instructions[i].clone());
+
+        let before = &dependencies.before[i];
+
+        step = before.iter().find(|&&(j, strict)| strict && levels[j] + 1 == levels[i])
+            .or_else(|| before.iter().find(|&&(j, strict)| !strict && levels[j] == levels[i]))
+            .map(|&(j, _)| j);
+    }
+
+    path.reverse();
+    path
+}
+
+struct Tally {
+    gate_counts: BTreeMap<String, usize>,
+    two_qubit_gates: usize,
+    measurements: usize
+}
+
+impl Tally {
+    fn walk(&mut self, instructions: &[Instruction]) {
+        for instruction in instructions {
+            if instruction.measured().is_some() {
+                self.measurements += 1;
+
+                continue;
+            }
+
+            match instruction {
+                Instruction::Gate(gate, _) => {
+                    let mut active = active_qubits(gate);
+
+                    // Some multiple of the identity, which gets counted as an I on its first qubit
+                    if active.is_empty() { active = vec![0] };
+
+                    *self.gate_counts.entry(gate_kind(gate, &active)).or_insert(0) += 1;
+
+                    if active.len() == 2 { self.two_qubit_gates += 1 };
+                },
+                Instruction::Circuit(circuit, _) => self.walk(circuit),
+                Instruction::Repeat(body, times) => (0..*times).for_each(|_| self.walk(std::slice::from_ref(&**body))),
+                Instruction::Dependent(body, _) | Instruction::While(_, body, _) | Instruction::RepeatUntil(body, _) => {
+                    self.walk(std::slice::from_ref(&**body))
+                },
+                _ => {}
+            }
+        }
+    }
+}
+
+// The standard gate's name if there is one, either way round for two qubit gates
+fn gate_kind(gate: &Vec<Vec<t!()>>, active: &[usize]) -> String {
+    let restricted = restrict(gate, active);
+
+    if let Some(name) = identify_gate(&restricted) { return name.to_string() };
+
+    if active.len() == 2 {
+        if let Some(name) = identify_gate(&restrict(gate, &[active[1], active[0]])) { return name.to_string() };
+    }
+
+    match active.len() {
+        1 => "U3".to_string(),
+        n => format!("U({})", n)
+    }
+}
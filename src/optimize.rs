@@ -0,0 +1,218 @@
+use ga_macros::*;
+
+use crate::*;
+
+// How close to a multiple of the identity a fused gate has to be before it's dropped
+const IDENTITY_TOLERANCE: f64 = 1e-10;
+
+/*
+        Peephole pass over simplified instructions. Each wire keeps a stack of the
+    instructions that touched it last, so a gate can see whether the one right before it on
+    all of its wires is a gate on exactly the same qubits. Single qubit gates get multiplied
+    into that gate, bigger gates only when the two cancel out, and anything that ends up as
+    the identity (up to a global phase) is dropped, which can expose the gate before it to
+    the next one. Measurements and everything classical act as barriers. Returns how many
+    gates were removed
+*/
+pub fn fuse_gates(instructions: &mut Vec<Instruction>) -> usize {
+    let wires = instructions.iter().map(|instruction| instruction.width()).max().unwrap_or(0);
+    let mut output: Vec<Option<Instruction>> = Vec::new();
+    let mut last: Vec<Vec<usize>> = vec![Vec::new(); wires];
+    let mut removed = 0;
+
+    for mut instruction in instructions.drain(..) {
+        if let Some(body) = body_mut(&mut instruction) {
+            removed += fuse_gates(body);
+        }
+
+        let (gate, index) = match instruction {
+            Instruction::Gate(gate, index) => (gate, index),
+            Instruction::Measure(index, _) => {
+                last[index].push(output.len());
+                output.push(Some(instruction));
+
+                continue;
+            },
+            _ => {
+                last.iter_mut().for_each(|stack| stack.push(output.len()));
+                output.push(Some(instruction));
+
+                continue;
+            }
+        };
+
+        let span = (gate.len() as f64).log2() as usize;
+        let wires = index..index + span;
+        let previous = last[index].last().copied().filter(|&j| wires.clone().all(|w| last[w].last() == Some(&j)));
+
+        let fused = previous.and_then(|j| match &output[j] {
+            Some(Instruction::Gate(earlier, earlier_index)) if *earlier_index == index && earlier.len() == gate.len() => {
+                let product = matrix_multiply(&gate, earlier);
+
+                if span == 1 || is_identity(&product) { Some((j, product)) } else { None }
+            },
+            _ => None
+        });
+
+        match fused {
+            Some((j, product)) if is_identity(&product) => {
+                output[j] = None;
+                wires.for_each(|w| { last[w].pop(); });
+                removed += 2;
+            },
+            Some((j, product)) => {
+                output[j] = Some(Instruction::Gate(product, index));
+                removed += 1;
+            },
+            None if is_identity(&gate) => removed += 1,
+            None => {
+                wires.for_each(|w| last[w].push(output.len()));
+                output.push(Some(Instruction::Gate(gate, index)));
+            }
+        }
+    }
+
+    instructions.extend(output.into_iter().flatten());
+
+    removed
+}
+
+// The instructions inside a dependent or repeated block, already simplified into a Circuit
+fn body_mut(instruction: &mut Instruction) -> Option<&mut Vec<Instruction>> {
+    let body = match instruction {
+        Instruction::Dependent(body, _) | Instruction::Repeat(body, _) | Instruction::While(_, body, _) | Instruction::RepeatUntil(body, _) => body,
+        _ => return None
+    };
+
+    match &mut **body {
+        Instruction::Circuit(circuit, _) => Some(circuit),
+        _ => None
+    }
+}
+
+// e^iθ I for some θ
+fn is_identity(matrix: &Vec<Vec<t!()>>) -> bool {
+    let d = matrix[0][0];
+
+    (d[0].powf(2.0) + d[1].powf(2.0) - 1.0).abs() < IDENTITY_TOLERANCE && matrix.iter().enumerate().all(|(i, row)| {
+        row.iter().enumerate().all(|(j, z)| {
+            let difference = if i == j { eq!(z - d) } else { *z };
+
+            difference[0].abs() < IDENTITY_TOLERANCE && difference[1].abs() < IDENTITY_TOLERANCE
+        })
+    })
+}
+
+// Gates spread over more qubits than this are assumed not to commute rather than checked
+const COMMUTATION_SPAN: usize = 6;
+
+/*
+        Like fuse_gates, but a gate can also slide back past anything it commutes with to
+    find its partner, so a Z on a CNOT's control still meets the Z before the CNOT and two
+    X corrections on its target cancel. Commutation gets checked numerically on just the
+    qubits the two gates cover. Diagonal gates commute with measurements on their wires as
+    well, everything else classical is still a barrier. Returns how many gates were removed
+*/
+pub fn fuse_commuting_gates(instructions: &mut Vec<Instruction>) -> usize {
+    let mut output: Vec<Option<Instruction>> = Vec::new();
+    let mut removed = 0;
+
+    for mut instruction in instructions.drain(..) {
+        if let Some(body) = body_mut(&mut instruction) {
+            removed += fuse_commuting_gates(body);
+        }
+
+        let (gate, index) = match instruction {
+            Instruction::Gate(gate, index) => (gate, index),
+            _ => {
+                output.push(Some(instruction));
+
+                continue;
+            }
+        };
+
+        let span = (gate.len() as f64).log2() as usize;
+        let wires = index..index + span;
+        let mut partner = None;
+
+        for j in (0..output.len()).rev() {
+            let passes = match &output[j] {
+                None => true,
+                Some(Instruction::Gate(earlier, earlier_index)) => {
+                    let earlier_wires = *earlier_index..*earlier_index + (earlier.len() as f64).log2() as usize;
+
+                    if earlier_wires.end <= wires.start || wires.end <= earlier_wires.start { continue };
+
+                    if *earlier_index == index && earlier.len() == gate.len() {
+                        let product = matrix_multiply(&gate, earlier);
+
+                        if span == 1 || is_identity(&product) {
+                            partner = Some((j, product));
+
+                            break;
+                        }
+                    }
+
+                    gates_commute(&gate, index, earlier, *earlier_index)
+                },
+                Some(Instruction::Measure(measured, _)) => !wires.contains(measured) || is_diagonal(&gate),
+                Some(_) => false
+            };
+
+            if !passes { break };
+        }
+
+        match partner {
+            Some((j, product)) if is_identity(&product) => {
+                output[j] = None;
+                removed += 2;
+            },
+            Some((j, product)) => {
+                output[j] = Some(Instruction::Gate(product, index));
+                removed += 1;
+            },
+            None if is_identity(&gate) => removed += 1,
+            None => output.push(Some(Instruction::Gate(gate, index)))
+        }
+    }
+
+    instructions.extend(output.into_iter().flatten());
+
+    removed
+}
+
+// Whether AB = BA for gates placed at their indices
+pub fn gates_commute(a: &Vec<Vec<t!()>>, a_index: usize, b: &Vec<Vec<t!()>>, b_index: usize) -> bool {
+    let a_span = (a.len() as f64).log2() as usize;
+    let b_span = (b.len() as f64).log2() as usize;
+
+    let start = a_index.min(b_index);
+    let span = (a_index + a_span).max(b_index + b_span) - start;
+
+    if span > COMMUTATION_SPAN { return false };
+
+    let a = widen(a, a_index - start, span);
+    let b = widen(b, b_index - start, span);
+
+    let ab = matrix_multiply(&a, &b);
+    let ba = matrix_multiply(&b, &a);
+
+    ab.iter().flatten().zip(ba.iter().flatten()).all(|(x, y)| {
+        (x[0] - y[0]).abs() < IDENTITY_TOLERANCE && (x[1] - y[1]).abs() < IDENTITY_TOLERANCE
+    })
+}
+
+// The gate with identities on either side so it covers span qubits
+fn widen(gate: &Vec<Vec<t!()>>, offset: usize, span: usize) -> Vec<Vec<t!()>> {
+    let gate_span = (gate.len() as f64).log2() as usize;
+    let before = identity(1 << offset);
+    let after = identity(1 << (span - offset - gate_span));
+
+    tensor_product_matrix(vec![&before, gate, &after])
+}
+
+fn is_diagonal(matrix: &Vec<Vec<t!()>>) -> bool {
+    matrix.iter().enumerate().all(|(i, row)| {
+        row.iter().enumerate().all(|(j, z)| i == j || (z[0].abs() < IDENTITY_TOLERANCE && z[1].abs() < IDENTITY_TOLERANCE))
+    })
+}
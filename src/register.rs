@@ -0,0 +1,65 @@
+use std::ops::Range;
+
+/*
+        A named run of qubits (like `alice` or `bob`) so circuits don't have to be written
+    against bare indices. Slicing keeps the name and the original numbering, so the first
+    qubit of alice.slice(1..3) is still labeled alice[1]
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct Register {
+    name: String,
+    qubits: Vec<usize>,
+    first: usize
+}
+
+impl Register {
+    pub fn new(name: &str, start: usize, size: usize) -> Self {
+        Register { name: name.to_string(), qubits: (start..start + size).collect(), first: 0 }
+    }
+
+    // Lays the registers out one after another starting from qubit 0
+    pub fn allocate(registers: &[(&str, usize)]) -> Vec<Register> {
+        let mut start = 0;
+
+        registers.iter().map(|&(name, size)| {
+            start += size;
+
+            Register::new(name, start - size, size)
+        }).collect()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn len(&self) -> usize {
+        self.qubits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.qubits.is_empty()
+    }
+
+    pub fn qubits(&self) -> &Vec<usize> {
+        &self.qubits
+    }
+
+    pub fn qubit(&self, i: usize) -> usize {
+        *self.qubits.get(i).unwrap_or_else(|| panic!("{}[{}] is out of range for a register of {} qubits", self.name, i, self.len()))
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> Register {
+        if range.end > self.len() || range.start > range.end {
+            panic!("{}[{}..{}] is out of range for a register of {} qubits", self.name, range.start, range.end, self.len())
+        };
+
+        Register { name: self.name.clone(), qubits: self.qubits[range.clone()].to_vec(), first: self.first + range.start }
+    }
+
+    // "alice[0]" style label for the i-th qubit
+    pub fn label(&self, i: usize) -> String {
+        self.qubit(i);
+
+        format!("{}[{}]", self.name, self.first + i)
+    }
+}
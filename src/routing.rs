@@ -0,0 +1,577 @@
+use std::collections::VecDeque;
+
+use crate::*;
+use crate::transpile::{active_qubits, restrict};
+
+// How many two qubit gates past the front layer get a say in which SWAP comes next
+const LOOKAHEAD_GATES: usize = 20;
+
+// Weight of those gates compared to the front layer
+const LOOKAHEAD_WEIGHT: f64 = 0.5;
+
+// How much more a SWAP costs for every recent SWAP on the same qubits
+const SWAP_DECAY: f64 = 0.001;
+
+// Forward and backward routing passes route makes to look for a better initial layout
+const LAYOUT_PASSES: usize = 3;
+
+/*
+        Which pairs of physical qubits can share a gate. Edges go both ways. A file lists one
+    edge per line as "0 1" or "0-1", with everything after a # ignored
+*/
+#[derive(Clone, Debug)]
+pub struct CouplingMap {
+    qubits: usize,
+    edges: Vec<(usize, usize)>,
+    distances: Vec<Vec<usize>>
+}
+
+impl CouplingMap {
+    pub fn new(qubits: usize, edges: &[(usize, usize)]) -> Self {
+        for &(a, b) in edges {
+            if a >= qubits || b >= qubits { panic!("edge {}-{} is outside of the {} qubits", a, b, qubits) };
+            if a == b { panic!("qubit {} can't be coupled to itself", a) };
+        }
+
+        let mut map = CouplingMap { qubits, edges: edges.to_vec(), distances: Vec::new() };
+
+        map.distances = (0..qubits).map(|q| map.breadth_first(q)).collect();
+
+        map
+    }
+
+    // 0 - 1 - 2 - ... - (n - 1)
+    pub fn line(qubits: usize) -> Self {
+        CouplingMap::new(qubits, &(1..qubits).map(|q| (q - 1, q)).collect::<Vec<(usize, usize)>>())
+    }
+
+    pub fn ring(qubits: usize) -> Self {
+        CouplingMap::new(qubits, &(0..qubits).map(|q| (q, (q + 1) % qubits)).collect::<Vec<(usize, usize)>>())
+    }
+
+    // Numbered row by row
+    pub fn grid(rows: usize, columns: usize) -> Self {
+        let mut edges = Vec::new();
+
+        for row in 0..rows {
+            for column in 0..columns {
+                let q = row * columns + column;
+
+                if column + 1 < columns { edges.push((q, q + 1)) };
+                if row + 1 < rows { edges.push((q, q + columns)) };
+            }
+        }
+
+        CouplingMap::new(rows * columns, &edges)
+    }
+
+    pub fn from_file(path: &str) -> Self {
+        let contents = std::fs::read_to_string(path).unwrap_or_else(|error| panic!("couldn't read the coupling map {}: {}", path, error));
+
+        CouplingMap::parse(&contents)
+    }
+
+    // The qubits are numbered up to the highest one any edge mentions
+    pub fn parse(contents: &str) -> Self {
+        let edges: Vec<(usize, usize)> = contents.lines().enumerate().filter_map(|(number, line)| {
+            let line = line.split('#').next().unwrap().trim();
+
+            if line.is_empty() { return None };
+
+            let ends: Vec<usize> = line.split(|c: char| c == '-' || c.is_whitespace())
+                .filter(|end| !end.is_empty())
+                .map(|end| end.parse().unwrap_or_else(|_| panic!("line {} of the coupling map has {} as a qubit", number + 1, end)))
+                .collect();
+
+            match ends[..] {
+                [a, b] => Some((a, b)),
+                _ => panic!("line {} of the coupling map should be a pair of qubits, not \"{}\"", number + 1, line)
+            }
+        }).collect();
+
+        let qubits = edges.iter().map(|&(a, b)| a.max(b) + 1).max().unwrap_or(0);
+
+        CouplingMap::new(qubits, &edges)
+    }
+
+    pub fn qubits(&self) -> usize {
+        self.qubits
+    }
+
+    pub fn edges(&self) -> &Vec<(usize, usize)> {
+        &self.edges
+    }
+
+    pub fn connected(&self, a: usize, b: usize) -> bool {
+        self.edges.iter().any(|&edge| edge == (a, b) || edge == (b, a))
+    }
+
+    pub fn neighbours(&self, qubit: usize) -> Vec<usize> {
+        self.edges.iter().filter_map(|&(a, b)| {
+            if a == qubit { Some(b) } else if b == qubit { Some(a) } else { None }
+        }).collect()
+    }
+
+    // The fewest edges between two qubits, usize::MAX if nothing connects them
+    pub fn distance(&self, a: usize, b: usize) -> usize {
+        self.distances[a][b]
+    }
+
+    fn breadth_first(&self, start: usize) -> Vec<usize> {
+        let mut distances = vec![usize::MAX; self.qubits];
+        let mut queue = VecDeque::from([start]);
+
+        distances[start] = 0;
+
+        while let Some(q) = queue.pop_front() {
+            for n in self.neighbours(q) {
+                if distances[n] == usize::MAX {
+                    distances[n] = distances[q] + 1;
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        distances
+    }
+}
+
+// Where every logical qubit currently sits on the device
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layout {
+    physical: Vec<usize>
+}
+
+impl Layout {
+    // physical[i] is where logical qubit i goes
+    pub fn new(physical: Vec<usize>) -> Self {
+        for (i, p) in physical.iter().enumerate() {
+            if physical[..i].contains(p) { panic!("two logical qubits can't both be on physical qubit {}", p) };
+        }
+
+        Layout { physical }
+    }
+
+    pub fn trivial(qubits: usize) -> Self {
+        Layout { physical: (0..qubits).collect() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.physical.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.physical.is_empty()
+    }
+
+    pub fn physical(&self, logical: usize) -> usize {
+        self.physical[logical]
+    }
+
+    // None for physical qubits nothing was placed on
+    pub fn logical(&self, physical: usize) -> Option<usize> {
+        self.physical.iter().position(|&p| p == physical)
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        for p in self.physical.iter_mut() {
+            if *p == a { *p = b } else if *p == b { *p = a };
+        }
+    }
+}
+
+/*
+        A routed experiment only has the physical qubits it uses, in the device's order:
+    its qubit i is physical qubit physical_qubits[i], and the layouts say which logical
+    qubit is on which physical one. measured keeps the routed and logical qubit of every
+    displayed measurement in the order they can happen, to translate them back with
+*/
+pub struct Routed {
+    pub experiment: Experiment,
+    pub physical_qubits: Vec<usize>,
+    pub initial_layout: Layout,
+    pub final_layout: Layout,
+    pub swaps: usize,
+    measured: Vec<(usize, usize)>
+}
+
+impl Routed {
+    // The physical qubit behind one of the routed experiment's qubits
+    pub fn physical(&self, qubit: usize) -> usize {
+        self.physical_qubits[qubit]
+    }
+
+    // The same records with the logical qubits the original experiment measured
+    pub fn logical_measurements(&self, measurements: &[Measurement]) -> Vec<Measurement> {
+        let mut expected = self.measured.iter();
+
+        // Conditional measurements that didn't happen get skipped over
+        measurements.iter().map(|Measurement(qubit, result, label)| {
+            let &(_, logical) = expected.find(|(q, _)| q == qubit).unwrap_or_else(|| {
+                panic!("the routed experiment never measures qubit {} at that point", qubit)
+            });
+
+            Measurement(logical, *result, label.clone())
+        }).collect()
+    }
+}
+
+/*
+        Routes from the best initial layout SABRE's forward and backward passes turn up.
+    Starting from the trivial layout, the experiment gets routed forwards and then its
+    instructions backwards from where that left the qubits, and the layout the backward pass
+    ends on is one that suits the start of the experiment. Every layout found on the way gets
+    tried and the one needing the fewest SWAPs wins
+*/
+pub fn route(experiment: &Experiment, coupling: &CouplingMap) -> Routed {
+    if experiment.qubits > coupling.qubits() {
+        panic!("{} qubits don't fit on a device with {}", experiment.qubits, coupling.qubits())
+    }
+
+    let backwards: Vec<Instruction> = experiment.instructions.iter().rev().cloned().collect();
+    let mut layouts = vec![Layout::trivial(experiment.qubits)];
+
+    for _ in 0..LAYOUT_PASSES {
+        let start = layouts.last().unwrap().clone();
+        let there = Router::new(coupling, start).finish(&experiment.instructions);
+        let back = Router::new(coupling, there).finish(&backwards);
+
+        if layouts.contains(&back) { break };
+
+        layouts.push(back);
+    }
+
+    layouts.into_iter()
+        .map(|layout| route_with_layout(experiment, coupling, layout))
+        .fold(None, |best: Option<Routed>, routed| match best {
+            Some(best) if best.swaps <= routed.swaps => Some(best),
+            _ => Some(routed)
+        })
+        .unwrap()
+}
+
+/*
+        SABRE style routing from a given layout. Gates run as soon as everything before them on their qubits has,
+    and when every gate that could run next is a two qubit gate on unconnected qubits, the
+    SWAP that brings those gates (and, less importantly, the ones after them) closest
+    together gets inserted. SWAPs on qubits that were just swapped cost a bit more so the
+    search doesn't go back and forth, and if it does anyway the first stuck gate's qubits
+    get walked towards each other along a shortest path. Conditional and repeated bodies
+    get routed on their own and swapped back at the end, so they leave the layout the way
+    they found it. Gates have to act on at most two qubits, transpile takes care of that.
+    Afterwards the physical qubits nothing touches get left out
+*/
+pub fn route_with_layout(experiment: &Experiment, coupling: &CouplingMap, layout: Layout) -> Routed {
+    let logical_qubits = experiment.qubits;
+
+    if logical_qubits > coupling.qubits() {
+        panic!("{} qubits don't fit on a device with {}", logical_qubits, coupling.qubits())
+    }
+
+    if layout.len() != logical_qubits { panic!("the layout places {} qubits, not {}", layout.len(), logical_qubits) };
+
+    if let Some(p) = layout.physical.iter().find(|&&p| p >= coupling.qubits()) {
+        panic!("the layout uses physical qubit {}, the device only has {}", p, coupling.qubits())
+    }
+
+    let mut router = Router::new(coupling, layout.clone());
+    let instructions = router.route(&experiment.instructions);
+
+    // Every qubit the logical ones start on, and whatever the instructions (SWAPs included) reach
+    let mut used = layout.physical.clone();
+
+    instructions.iter().for_each(|instruction| physical_qubits(instruction, &mut used));
+    used.sort();
+    used.dedup();
+
+    let mut position = vec![None; coupling.qubits()];
+
+    used.iter().enumerate().for_each(|(i, &p)| position[p] = Some(i));
+
+    // Each logical bit of the initial state moves to its physical qubit, the rest start at |0⟩
+    let qubits = used.len();
+    let mut state = vec![[0.0, 0.0]; 1 << qubits];
+
+    for (basis, amplitude) in experiment.state.iter().enumerate() {
+        let moved = (0..logical_qubits).fold(0, |accum, q| {
+            accum | (((basis >> (logical_qubits - 1 - q)) & 1) << (qubits - 1 - position[layout.physical(q)].unwrap()))
+        });
+
+        state[moved] = *amplitude;
+    }
+
+    Routed {
+        experiment: Experiment { qubits, state, instructions: instructions.iter().map(|instruction| compact(instruction, &position)).collect() },
+        physical_qubits: used,
+        initial_layout: layout,
+        final_layout: router.layout,
+        swaps: router.swaps,
+        measured: router.measured.iter().map(|&(p, logical)| (position[p].unwrap(), logical)).collect()
+    }
+}
+
+fn physical_qubits(instruction: &Instruction, into: &mut Vec<usize>) {
+    match instruction {
+        Instruction::Gate(gate, index) => into.extend(active_qubits(gate).iter().map(|q| q + index)),
+        Instruction::Circuit(circuit, _) => circuit.iter().for_each(|instruction| physical_qubits(instruction, into)),
+        Instruction::Dependent(body, _) | Instruction::Repeat(body, _) | Instruction::While(_, body, _) | Instruction::RepeatUntil(body, _) => {
+            physical_qubits(body, into)
+        },
+        _ => into.extend(instruction.measured().map(|(qubit, _, _)| qubit))
+    }
+}
+
+// The instruction on the routed experiment's qubits, position[p] being where physical qubit p went
+fn compact(instruction: &Instruction, position: &Vec<Option<usize>>) -> Instruction {
+    let moved = |p: usize| position[p].unwrap();
+
+    match instruction {
+        Instruction::Gate(gate, index) => {
+            let active = active_qubits(gate);
+            let (matrix, start) = embed_gate(&restrict(gate, &active), &active.iter().map(|q| moved(q + index)).collect::<Vec<usize>>());
+
+            Instruction::Gate(matrix, start)
+        },
+        Instruction::Measure(qubit, display) => Instruction::Measure(moved(*qubit), *display),
+        Instruction::Labeled(label, measure) => Instruction::Labeled(label.clone(), Box::new(compact(measure, position))),
+        Instruction::Circuit(circuit, shift) => Instruction::Circuit(circuit.iter().map(|instruction| compact(instruction, position)).collect(), *shift),
+        Instruction::Dependent(body, measurement) => Instruction::Dependent(Box::new(compact(body, position)), *measurement),
+        Instruction::Repeat(body, times) => Instruction::Repeat(Box::new(compact(body, position)), *times),
+        Instruction::While(condition, body, max) => Instruction::While(condition.clone(), Box::new(compact(body, position)), *max),
+        Instruction::RepeatUntil(body, condition) => Instruction::RepeatUntil(Box::new(compact(body, position)), condition.clone()),
+        _ => instruction.clone()
+    }
+}
+
+struct Router<'a> {
+    coupling: &'a CouplingMap,
+    layout: Layout,
+    swaps: usize,
+    history: Vec<(usize, usize)>,
+    measured: Vec<(usize, usize)>
+}
+
+impl<'a> Router<'a> {
+    fn new(coupling: &'a CouplingMap, layout: Layout) -> Self {
+        Router { coupling, layout, swaps: 0, history: Vec::new(), measured: Vec::new() }
+    }
+
+    // Where routing the instructions leaves the logical qubits
+    fn finish(mut self, instructions: &Vec<Instruction>) -> Layout {
+        self.route(instructions);
+        self.layout
+    }
+
+    fn route(&mut self, instructions: &Vec<Instruction>) -> Vec<Instruction> {
+        let qubits = self.layout.len();
+        let logical: Vec<Vec<usize>> = instructions.iter().map(|instruction| self.logical_qubits(instruction)).collect();
+
+        let mut done = vec![false; instructions.len()];
+        let mut output = Vec::new();
+        let mut decay = vec![1.0; self.coupling.qubits()];
+        let mut stalled = 0;
+
+        while done.iter().any(|d| !d) {
+            let mut blocked = vec![false; qubits];
+            let mut front = Vec::new();
+            let mut progress = false;
+
+            // Measurements stay in order, that's how they're numbered
+            let mut measurement_blocked = false;
+
+            for (i, qubits) in logical.iter().enumerate() {
+                if done[i] { continue };
+
+                let measures = instructions[i].measurements() > 0;
+
+                if qubits.iter().all(|&q| !blocked[q]) && !(measures && measurement_blocked) {
+                    if self.executable(qubits) {
+                        output.extend(self.place(&instructions[i], qubits));
+                        done[i] = true;
+                        progress = true;
+
+                        continue;
+                    }
+
+                    front.push((qubits[0], qubits[1]));
+                }
+
+                qubits.iter().for_each(|&q| blocked[q] = true);
+                measurement_blocked |= measures;
+            }
+
+            if progress {
+                decay.iter_mut().for_each(|d| *d = 1.0);
+                stalled = 0;
+
+                continue;
+            }
+
+            let lookahead: Vec<(usize, usize)> = logical.iter().enumerate()
+                .filter(|&(i, qubits)| !done[i] && qubits.len() == 2 && !front.contains(&(qubits[0], qubits[1])))
+                .take(LOOKAHEAD_GATES)
+                .map(|(_, qubits)| (qubits[0], qubits[1]))
+                .collect();
+
+            let (a, b) = if stalled > 2 * self.coupling.qubits() {
+                self.step_closer(front[0])
+            } else {
+                self.best_swap(&front, &lookahead, &decay)
+            };
+
+            decay[a] += SWAP_DECAY;
+            decay[b] += SWAP_DECAY;
+            stalled += 1;
+
+            output.push(self.swap(a, b));
+        }
+
+        output
+    }
+
+    // Logical qubits an instruction needs, everything for ones that aren't gates or measurements
+    fn logical_qubits(&self, instruction: &Instruction) -> Vec<usize> {
+        match instruction {
+            Instruction::Gate(gate, index) => {
+                let active: Vec<usize> = active_qubits(gate).iter().map(|q| index + q).collect();
+
+                if active.len() > 2 { panic!("routing needs gates on at most two qubits, transpile the experiment first") };
+
+                active
+            },
+            _ => match instruction.measured() {
+                Some((qubit, _, _)) => vec![qubit],
+                None => (0..self.layout.len()).collect()
+            }
+        }
+    }
+
+    fn executable(&self, qubits: &[usize]) -> bool {
+        match qubits {
+            [a, b] => self.coupling.connected(self.layout.physical(*a), self.layout.physical(*b)),
+            _ => true
+        }
+    }
+
+    // The instruction moved onto the qubits the layout currently has its logical ones on
+    fn place(&mut self, instruction: &Instruction, qubits: &[usize]) -> Vec<Instruction> {
+        match instruction {
+            Instruction::Gate(gate, index) => {
+                if qubits.is_empty() { return vec![] };
+
+                let active: Vec<usize> = qubits.iter().map(|q| q - index).collect();
+                let physical: Vec<usize> = qubits.iter().map(|&q| self.layout.physical(q)).collect();
+                let (matrix, index) = embed_gate(&restrict(gate, &active), &physical);
+
+                vec![Instruction::Gate(matrix, index)]
+            },
+            Instruction::Measure(qubit, display) => {
+                let physical = self.layout.physical(*qubit);
+
+                if *display { self.measured.push((physical, *qubit)) };
+
+                vec![Instruction::Measure(physical, *display)]
+            },
+            Instruction::Labeled(label, measure) => {
+                let mut placed = self.place(measure, qubits);
+
+                vec![Instruction::Labeled(label.clone(), Box::new(placed.remove(0)))]
+            },
+            Instruction::Dependent(body, measurement) => vec![Instruction::Dependent(Box::new(self.route_body(body, true)), *measurement)],
+            Instruction::Repeat(body, times) => vec![Instruction::Repeat(Box::new(self.route_body(body, false)), *times)],
+            Instruction::While(condition, body, max) => vec![Instruction::While(condition.clone(), Box::new(self.route_body(body, false)), *max)],
+            Instruction::RepeatUntil(body, condition) => vec![Instruction::RepeatUntil(Box::new(self.route_body(body, false)), condition.clone())],
+            _ => vec![instruction.clone()]
+        }
+    }
+
+    // Loop bodies' measurements are thrown away after every iteration, so only conditional
+    // ones get recorded
+    fn route_body(&mut self, body: &Instruction, record: bool) -> Instruction {
+        let (circuit, shift) = match body {
+            Instruction::Circuit(circuit, shift) => (circuit, *shift),
+            _ => return body.clone()
+        };
+
+        let (start, recorded) = (self.history.len(), self.measured.len());
+        let mut routed = self.route(circuit);
+
+        if !record { self.measured.truncate(recorded) };
+
+        // Undoing the body's SWAPs in reverse puts every qubit back where it was
+        for (a, b) in self.history.split_off(start).into_iter().rev() {
+            routed.push(self.swap(a, b));
+        }
+
+        self.history.truncate(start);
+
+        Instruction::Circuit(routed, shift)
+    }
+
+    fn best_swap(&self, front: &[(usize, usize)], lookahead: &[(usize, usize)], decay: &[f64]) -> (usize, usize) {
+        let mut candidates: Vec<(usize, usize)> = Vec::new();
+
+        for &(a, b) in front {
+            for p in [self.layout.physical(a), self.layout.physical(b)] {
+                for n in self.coupling.neighbours(p) {
+                    let edge = (p.min(n), p.max(n));
+
+                    if !candidates.contains(&edge) { candidates.push(edge) };
+                }
+            }
+        }
+
+        let score = |(a, b): (usize, usize)| {
+            let mut layout = self.layout.clone();
+
+            layout.swap(a, b);
+
+            let total = |gates: &[(usize, usize)]| gates.iter().map(|&(x, y)| {
+                self.coupling.distance(layout.physical(x), layout.physical(y)) as f64
+            }).sum::<f64>() / gates.len().max(1) as f64;
+
+            decay[a].max(decay[b]) * (total(front) + LOOKAHEAD_WEIGHT * total(lookahead))
+        };
+
+        candidates.into_iter()
+            .map(|edge| (edge, score(edge)))
+            .fold(None, |best: Option<((usize, usize), f64)>, (edge, cost)| match best {
+                Some((_, lowest)) if lowest <= cost => best,
+                _ => Some((edge, cost))
+            })
+            .map(|(edge, _)| edge)
+            .expect("a stuck gate has to have a qubit with a neighbour")
+    }
+
+    // One SWAP along a shortest path between the qubits of a stuck gate
+    fn step_closer(&self, (a, b): (usize, usize)) -> (usize, usize) {
+        let (from, to) = (self.layout.physical(a), self.layout.physical(b));
+
+        (from, self.path(from, to)[1])
+    }
+
+    fn path(&self, from: usize, to: usize) -> Vec<usize> {
+        if self.coupling.distance(from, to) == usize::MAX { panic!("physical qubits {} and {} aren't connected", from, to) };
+
+        let mut path = vec![from];
+
+        while *path.last().unwrap() != to {
+            let here = *path.last().unwrap();
+            let next = self.coupling.neighbours(here).into_iter().find(|&n| self.coupling.distance(n, to) < self.coupling.distance(here, to)).unwrap();
+
+            path.push(next);
+        }
+
+        path
+    }
+
+    fn swap(&mut self, a: usize, b: usize) -> Instruction {
+        let (matrix, index) = embed_gate(&SWAP, &[a, b]);
+
+        self.layout.swap(a, b);
+        self.history.push((a, b));
+        self.swaps += 1;
+
+        Instruction::Gate(matrix, index)
+    }
+}
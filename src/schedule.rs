@@ -0,0 +1,167 @@
+use crate::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SchedulePolicy {
+    // Everything as early as it can go
+    ASAP,
+    // Everything as late as it can go without needing more moments than ASAP
+    ALAP
+}
+
+/*
+        Groups the instructions into moments, instructions in the same moment acting on different
+    qubits so they could all happen at once. Gates only take up the qubits they really act on,
+    repeated blocks are written out and conditional blocks are split into one conditional
+    instruction each, which has to come after the measurement it depends on. Loops stay in one
+    piece and take up every qubit their body touches. Measurements and loops keep their order
+    (some may share a moment), so measurement numbers don't change. Within a moment the
+    instructions are in their original order
+*/
+pub fn schedule(instructions: &Vec<Instruction>, qubits: usize, policy: SchedulePolicy) -> Vec<Vec<Instruction>> {
+    let dependencies = dependencies(instructions, qubits);
+    let levels = match policy {
+        SchedulePolicy::ASAP => asap(&dependencies),
+        SchedulePolicy::ALAP => alap(&dependencies)
+    };
+
+    let mut moments = vec![Vec::new(); levels.iter().max().map_or(0, |level| level + 1)];
+
+    for (instruction, level) in dependencies.instructions.into_iter().zip(levels) {
+        moments[level].push(instruction);
+    }
+
+    moments
+}
+
+impl Experiment {
+    pub fn moments(&self, policy: SchedulePolicy) -> Vec<Vec<Instruction>> {
+        schedule(&self.instructions, self.qubits, policy)
+    }
+}
+
+// The written out instructions, the qubits each one takes up and what it has to wait for
+pub(crate) struct Dependencies {
+    pub(crate) instructions: Vec<Instruction>,
+    pub(crate) qubits: Vec<Vec<usize>>,
+    // Earlier instructions, and whether this one needs a later moment than them rather than
+    // possibly the same one
+    pub(crate) before: Vec<Vec<(usize, bool)>>
+}
+
+pub(crate) fn dependencies(instructions: &Vec<Instruction>, qubits: usize) -> Dependencies {
+    let mut flat = Vec::new();
+
+    instructions.iter().for_each(|instruction| write_out(instruction, &mut flat));
+
+    let mut last: Vec<Option<usize>> = vec![None; qubits];
+    let mut last_ordered = None;
+    let mut measured = Vec::new();
+    let mut dependencies = Dependencies { instructions: Vec::new(), qubits: Vec::new(), before: Vec::new() };
+
+    for (i, instruction) in flat.into_iter().enumerate() {
+        let touched = touched(&instruction);
+        let mut before: Vec<(usize, bool)> = touched.iter().filter_map(|&q| last[q]).map(|j| (j, true)).collect();
+
+        before.extend(waits_for(&instruction).iter().filter_map(|&measurement| measured.get(measurement)).map(|&j| (j, true)));
+
+        if is_ordered(&instruction) {
+            before.extend(last_ordered.map(|j| (j, false)));
+            last_ordered = Some(i);
+        }
+
+        if instruction.measured().is_some() { measured.push(i) };
+
+        touched.iter().for_each(|&q| last[q] = Some(i));
+
+        dependencies.instructions.push(instruction);
+        dependencies.qubits.push(touched);
+        dependencies.before.push(before);
+    }
+
+    dependencies
+}
+
+pub(crate) fn asap(dependencies: &Dependencies) -> Vec<usize> {
+    let mut levels: Vec<usize> = Vec::new();
+
+    for before in dependencies.before.iter() {
+        let level = before.iter().map(|&(j, strict)| levels[j] + strict as usize).max().unwrap_or(0);
+
+        levels.push(level);
+    }
+
+    levels
+}
+
+// How far each instruction is from the end, then counted from the front
+fn alap(dependencies: &Dependencies) -> Vec<usize> {
+    let count = dependencies.instructions.len();
+    let mut remaining = vec![0; count];
+
+    for i in (0..count).rev() {
+        for &(j, strict) in dependencies.before[i].iter() {
+            remaining[j] = remaining[j].max(remaining[i] + strict as usize);
+        }
+    }
+
+    let last = remaining.iter().copied().max().unwrap_or(0);
+
+    remaining.iter().map(|r| last - r).collect()
+}
+
+fn write_out(instruction: &Instruction, into: &mut Vec<Instruction>) {
+    match instruction {
+        Instruction::Circuit(circuit, _) => circuit.iter().for_each(|instruction| write_out(instruction, into)),
+        Instruction::Repeat(body, times) => (0..*times).for_each(|_| write_out(body, into)),
+        Instruction::Dependent(body, measurement) => {
+            let mut conditional = Vec::new();
+
+            write_out(body, &mut conditional);
+            into.extend(conditional.into_iter().map(|instruction| Instruction::Dependent(Box::new(instruction), *measurement)));
+        },
+        _ => into.push(instruction.clone())
+    }
+}
+
+fn touched(instruction: &Instruction) -> Vec<usize> {
+    let mut qubits = match instruction {
+        Instruction::Gate(gate, index) => {
+            let active = active_qubits(gate);
+
+            // Some multiple of the identity, which is kept on its first qubit
+            if active.is_empty() { vec![*index] } else { active.iter().map(|q| q + index).collect() }
+        },
+        Instruction::Circuit(circuit, _) => circuit.iter().flat_map(touched).collect(),
+        Instruction::Dependent(body, _) | Instruction::Repeat(body, _) | Instruction::While(_, body, _) | Instruction::RepeatUntil(body, _) => touched(body),
+        _ => instruction.measured().map(|(index, _, _)| vec![index]).unwrap_or_default()
+    };
+
+    qubits.sort();
+    qubits.dedup();
+    qubits
+}
+
+fn waits_for(instruction: &Instruction) -> Vec<usize> {
+    match instruction {
+        Instruction::Dependent(body, measurement) => [vec![*measurement], waits_for(body)].concat(),
+        Instruction::While(condition, _, _) | Instruction::RepeatUntil(_, condition) => looked_at(condition),
+        _ => vec![]
+    }
+}
+
+// Whatever adds to or reads the list of measurement results
+fn is_ordered(instruction: &Instruction) -> bool {
+    match instruction {
+        Instruction::Dependent(body, _) => is_ordered(body),
+        Instruction::While(_, _, _) | Instruction::RepeatUntil(_, _) => true,
+        _ => instruction.measured().is_some()
+    }
+}
+
+fn looked_at(condition: &Condition) -> Vec<usize> {
+    match condition {
+        Condition::One(measurement) | Condition::Zero(measurement) => vec![*measurement],
+        Condition::Not(condition) => looked_at(condition),
+        Condition::All(conditions) | Condition::Any(conditions) => conditions.iter().flat_map(looked_at).collect()
+    }
+}
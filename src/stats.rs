@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+
+use crate::Counts;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interval {
+    Wilson,
+    ClopperPearson
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChiSquare {
+    pub statistic: f64,
+    pub degrees_of_freedom: usize,
+    pub p_value: f64
+}
+
+impl ChiSquare {
+    // True when the sample is consistent with the expected distribution at the given confidence
+    pub fn passes(&self, confidence: f64) -> bool {
+        self.p_value >= 1.0 - confidence
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TotalVariation {
+    pub distance: f64,
+    pub outcomes: usize,
+    pub shots: usize
+}
+
+impl TotalVariation {
+    /*
+            The largest distance sampling noise explains at the given confidence, using the
+        bound on the L1 deviation of an empirical distribution with k outcomes:
+        P(‖p̂ - p‖₁ ≥ ε) ≤ (2^k - 2) e^(-nε²/2)
+    */
+    pub fn threshold(&self, confidence: f64) -> f64 {
+        let outcomes = self.outcomes.max(2) as f64;
+        let log_terms = if outcomes < 64.0 { (2.0f64.powf(outcomes) - 2.0).ln() } else { outcomes * std::f64::consts::LN_2 };
+
+        (2.0 * (log_terms - (1.0 - confidence).ln()) / self.shots as f64).powf(0.5) / 2.0
+    }
+
+    // True when the sample is consistent with the expected distribution at the given confidence
+    pub fn passes(&self, confidence: f64) -> bool {
+        self.distance <= self.threshold(confidence)
+    }
+}
+
+impl Counts {
+    pub fn interval(&self, bitstring: &str, confidence: f64, method: Interval) -> (f64, f64) {
+        match method {
+            Interval::Wilson => wilson_interval(self.count(bitstring), self.shots(), confidence),
+            Interval::ClopperPearson => clopper_pearson_interval(self.count(bitstring), self.shots(), confidence)
+        }
+    }
+
+    pub fn intervals(&self, confidence: f64, method: Interval) -> BTreeMap<String, (f64, f64)> {
+        self.counts().keys().map(|key| (key.clone(), self.interval(key, confidence, method))).collect()
+    }
+
+    pub fn chi_square_test(&self, expected: &BTreeMap<String, f64>) -> ChiSquare {
+        let shots = self.shots() as f64;
+        let mut statistic = 0.0;
+        let mut categories = 0;
+
+        for (key, &probability) in expected.iter() {
+            if probability <= 0.0 { continue };
+
+            let difference = self.count(key) as f64 - shots * probability;
+
+            statistic += difference * difference / (shots * probability);
+            categories += 1;
+        }
+
+        // Outcomes that should be impossible
+        if self.counts().keys().any(|key| expected.get(key).copied().unwrap_or(0.0) <= 0.0) {
+            statistic = f64::INFINITY;
+        }
+
+        let degrees_of_freedom = categories.max(2) - 1;
+
+        ChiSquare {
+            statistic,
+            degrees_of_freedom,
+            p_value: if statistic.is_finite() {
+                upper_regularized_gamma(degrees_of_freedom as f64 / 2.0, statistic / 2.0)
+            } else {
+                0.0
+            }
+        }
+    }
+
+    // The empirical total variation distance, along with what it takes to judge it
+    pub fn total_variation_test(&self, expected: &BTreeMap<String, f64>) -> TotalVariation {
+        let mut keys: Vec<&String> = expected.keys().filter(|key| expected[*key] > 0.0).collect();
+
+        for key in self.counts().keys() {
+            if !keys.contains(&key) { keys.push(key) };
+        }
+
+        let distance = keys.iter().map(|key| {
+            (self.probability(key) - expected.get(*key).copied().unwrap_or(0.0)).abs()
+        }).sum::<f64>() / 2.0;
+
+        TotalVariation { distance, outcomes: keys.len(), shots: self.shots() }
+    }
+}
+
+pub fn wilson_interval(successes: usize, trials: usize, confidence: f64) -> (f64, f64) {
+    if trials == 0 { return (0.0, 1.0) };
+
+    let n = trials as f64;
+    let p = successes as f64 / n;
+    let z = normal_quantile(1.0 - (1.0 - confidence) / 2.0);
+    let denominator = 1.0 + z * z / n;
+
+    let center = (p + z * z / (2.0 * n)) / denominator;
+    let half_width = z / denominator * (p * (1.0 - p) / n + z * z / (4.0 * n * n)).powf(0.5);
+
+    ((center - half_width).max(0.0), (center + half_width).min(1.0))
+}
+
+pub fn clopper_pearson_interval(successes: usize, trials: usize, confidence: f64) -> (f64, f64) {
+    let alpha = 1.0 - confidence;
+    let x = successes as f64;
+    let n = trials as f64;
+
+    (
+        if successes == 0 { 0.0 } else { beta_quantile(alpha / 2.0, x, n - x + 1.0) },
+        if successes == trials { 1.0 } else { beta_quantile(1.0 - alpha / 2.0, x + 1.0, n - x) }
+    )
+}
+
+// Acklam's rational approximation of the inverse standard normal cdf
+pub fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [-3.969683028665376e1, 2.209460984245205e2, -2.759285104469687e2, 1.38357751867269e2, -3.066479806614716e1, 2.506628277459239];
+    const B: [f64; 5] = [-5.447609879822406e1, 1.615858368580409e2, -1.556989798598866e2, 6.680131188771972e1, -1.328068155288572e1];
+    const C: [f64; 6] = [-7.784894002430293e-3, -3.223964580411365e-1, -2.400758277161838, -2.549732539343734, 4.374664141464968, 2.938163982698783];
+    const D: [f64; 4] = [7.784695709041462e-3, 3.224671290700398e-1, 2.445134137142996, 3.754408661907416];
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5]) /
+        ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+
+    if p < 0.02425 {
+        tail((-2.0 * p.ln()).powf(0.5))
+    } else if p > 1.0 - 0.02425 {
+        -tail((-2.0 * (1.0 - p).ln()).powf(0.5))
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q /
+        (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+// Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9, 676.5203681218851, -1259.1392167224028, 771.323_428_777_653_1,
+        -176.615_029_162_140_6, 12.507343278686905, -0.13857109526572012, 9.984_369_578_019_572e-6,
+        1.5056327351493116e-7
+    ];
+
+    if x < 0.5 {
+        return (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = COEFFICIENTS[1..].iter().enumerate().fold(COEFFICIENTS[0], |sum, (i, c)| sum + c / (x + i as f64 + 1.0));
+
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+// Q(a, x) = Γ(a, x) / Γ(a), the chi-square survival function when a = k/2 and x = χ²/2
+fn upper_regularized_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 { return 1.0 };
+
+    let prefactor = (-x + a * x.ln() - ln_gamma(a)).exp();
+
+    if x < a + 1.0 {
+        // Series for the lower function
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        let mut n = a;
+
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+
+            if term.abs() < sum.abs() * 1e-15 { break };
+        }
+
+        1.0 - sum * prefactor
+    } else {
+        // Continued fraction (modified Lentz)
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / 1e-300;
+        let mut d = 1.0 / b;
+        let mut h = d;
+
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < 1e-300 { d = 1e-300 };
+            c = b + an / c;
+            if c.abs() < 1e-300 { c = 1e-300 };
+            d = 1.0 / d;
+
+            let delta = d * c;
+            h *= delta;
+
+            if (delta - 1.0).abs() < 1e-15 { break };
+        }
+
+        prefactor * h
+    }
+}
+
+// I_x(a, b)
+fn regularized_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 { return 0.0 };
+    if x >= 1.0 { return 1.0 };
+
+    let prefactor = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+
+    if x < (a + 1.0) / (a + b + 2.0) {
+        prefactor * beta_continued_fraction(x, a, b) / a
+    } else {
+        1.0 - prefactor * beta_continued_fraction(1.0 - x, b, a) / b
+    }
+}
+
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < 1e-300 { d = 1e-300 };
+    d = 1.0 / d;
+    let mut h = d;
+
+    for m in 1..1000 {
+        let m = m as f64;
+
+        for an in [
+            m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0))
+        ] {
+            d = 1.0 + an * d;
+            if d.abs() < 1e-300 { d = 1e-300 };
+            c = 1.0 + an / c;
+            if c.abs() < 1e-300 { c = 1e-300 };
+            d = 1.0 / d;
+            h *= d * c;
+        }
+
+        if (d * c - 1.0).abs() < 1e-15 { break };
+    }
+
+    h
+}
+
+// Bisection, I_x is monotonic in x
+fn beta_quantile(p: f64, a: f64, b: f64) -> f64 {
+    let (mut low, mut high) = (0.0, 1.0);
+
+    for _ in 0..100 {
+        let mid = (low + high) / 2.0;
+
+        if regularized_beta(mid, a, b) < p { low = mid } else { high = mid };
+    }
+
+    (low + high) / 2.0
+}
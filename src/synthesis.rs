@@ -0,0 +1,308 @@
+use ga_macros::*;
+
+use crate::*;
+
+// Columns shorter than this are treated as missing and replaced while orthonormalizing
+const COLUMN_TOLERANCE: f64 = 1e-13;
+
+/*
+        A unitary on any number of qubits as single qubit gates and CNOTs, the circuit acting
+    on qubits 0 up to n - 1 and equal to the unitary up to a global phase
+*/
+#[derive(Clone, Debug)]
+pub struct UnitarySynthesis {
+    pub circuit: Vec<Instruction>,
+    pub cnots: usize
+}
+
+/*
+        Quantum Shannon decomposition. The cosine-sine decomposition splits U into a block
+    diagonal unitary, Ry rotations on qubit 0 multiplexed over the rest and another block
+    diagonal unitary. Every block diagonal one [[A, 0], [0, B]] is a gate on the lower qubits
+    multiplexed over qubit 0, and demultiplexes into V (I ⊗ D) W with multiplexed Rz
+    rotations in the middle, where V D² V† = A B† and W = D V† B. That leaves four unitaries
+    on one qubit less, down to two qubits where kak_decompose takes over. Multiplexed
+    rotations over k qubits take 2^k CNOTs, so n qubits end up with at most
+    (9/16) 4^n - (3/2) 2^n CNOTs before fuse_gates gets to cancel some
+*/
+pub fn synthesize_unitary(gate: &Vec<Vec<t!()>>) -> UnitarySynthesis {
+    let qubits = (gate.len() as f64).log2() as usize;
+
+    if qubits == 0 || 1 << qubits != gate.len() || gate.iter().any(|row| row.len() != gate.len()) {
+        panic!("only square matrices with a power of two size are gates, not one of size {}", gate.len())
+    }
+
+    let mut circuit = shannon(gate, 0);
+
+    fuse_gates(&mut circuit);
+
+    let cnots = circuit.iter().filter(|instruction| matches!(instruction, Instruction::Gate(gate, _) if gate.len() > 2)).count();
+
+    UnitarySynthesis { circuit, cnots }
+}
+
+// The unitary acting on qubits first up to first + n - 1
+fn shannon(gate: &Vec<Vec<t!()>>, first: usize) -> Vec<Instruction> {
+    match gate.len() {
+        2 => return vec![Instruction::Gate(gate.clone(), first)],
+        4 => return kak_decompose(gate).circuit.iter().map(|instruction| instruction.shifted(first)).collect(),
+        _ => {}
+    };
+
+    let (left, angles, right) = cosine_sine(gate);
+    let controls: Vec<usize> = (first + 1..first + (gate.len() as f64).log2() as usize).collect();
+
+    let mut circuit = demultiplex(&right.0, &right.1, first);
+
+    circuit.extend(multiplexed_rotation(&ry, &angles, &controls, first));
+    circuit.extend(demultiplex(&left.0, &left.1, first));
+
+    circuit
+}
+
+type Blocks = (Vec<Vec<t!()>>, Vec<Vec<t!()>>);
+
+/*
+        U = diag(L0, L1) [[C, -S], [S, C]] diag(R0, R1), returned as ((L0, L1), θ, (R0, R1))
+    with C = cos(θ/2) and S = sin(θ/2). R0† comes from the eigenvectors of U00† U00, whose
+    images under U00 and U10 are L0 C and L1 S. Those columns get normalized and
+    orthonormalized longest first, so short ones (where their direction can't be trusted)
+    are fixed up by the others without changing U much. R1 is read off U11 where C is the
+    bigger one and off U01 where S is
+*/
+fn cosine_sine(gate: &Vec<Vec<t!()>>) -> (Blocks, Vec<f64>, Blocks) {
+    let half = gate.len() / 2;
+    let block = |row: usize, column: usize| -> Vec<Vec<t!()>> {
+        (0..half).map(|i| (0..half).map(|j| gate[row * half + i][column * half + j]).collect()).collect()
+    };
+
+    let (u00, u01, u10, u11) = (block(0, 0), block(0, 1), block(1, 0), block(1, 1));
+    let (_, vectors) = hermitian_eigen(&matrix_multiply(&dagger(&u00), &u00));
+
+    let r0: Vec<Vec<t!()>> = vectors.iter().map(|v| v.iter().map(|z| complement(*z)).collect()).collect();
+    let columns = |block: &Vec<Vec<t!()>>| -> Vec<Vec<t!()>> { vectors.iter().map(|v| matrix_vector(block, v)).collect() };
+
+    let (top, bottom) = (columns(&u00), columns(&u10));
+    let (c, s): (Vec<f64>, Vec<f64>) = top.iter().zip(bottom.iter()).map(|(x, y)| (length(x), length(y))).unzip();
+
+    let l0 = orthonormal_columns(top, &c);
+    let l1 = orthonormal_columns(bottom, &s);
+
+    let (l1_u11, l0_u01) = (matrix_multiply(&dagger(&l1), &u11), matrix_multiply(&dagger(&l0), &u01));
+
+    let r1 = (0..half).map(|j| {
+        if c[j] >= s[j] {
+            l1_u11[j].iter().map(|z| eq!(z / #c[j])).collect()
+        } else {
+            let minus = -s[j];
+
+            l0_u01[j].iter().map(|z| eq!(z / #minus)).collect()
+        }
+    }).collect();
+
+    let angles = c.iter().zip(s.iter()).map(|(c, s)| 2.0 * s.atan2(*c)).collect();
+
+    ((l0, l1), angles, (r0, r1))
+}
+
+// The vectors normalized into the columns of a unitary, longest first
+fn orthonormal_columns(vectors: Vec<Vec<t!()>>, lengths: &[f64]) -> Vec<Vec<t!()>> {
+    let n = vectors.len();
+    let mut order: Vec<usize> = (0..n).collect();
+
+    order.sort_by(|&i, &j| lengths[j].partial_cmp(&lengths[i]).unwrap());
+
+    let mut basis: Vec<Option<Vec<t!()>>> = vec![None; n];
+    let mut done: Vec<Vec<t!()>> = Vec::new();
+
+    // Missing directions get filled in from the standard basis
+    let candidates = order.iter().map(|&i| (i, vectors[i].clone())).chain((0..n).map(|k| {
+        (n, (0..n).map(|j| if j == k { eq!(1) } else { eq!(0) }).collect())
+    }));
+
+    let mut missing: Vec<usize> = Vec::new();
+
+    for (i, vector) in candidates {
+        if done.len() == n { break };
+
+        let mut v = vector;
+
+        for _ in 0..2 {
+            for u in done.iter() {
+                let overlap = inner_product(u, &v);
+
+                v = v.iter().zip(u.iter()).map(|(x, y)| eq!(x - overlap * y)).collect();
+            }
+        }
+
+        let size = length(&v);
+
+        if i < n && size <= COLUMN_TOLERANCE { missing.push(i); continue };
+        if i == n && size <= 1e-6 { continue };
+
+        let v: Vec<t!()> = v.iter().map(|z| eq!(z / #size)).collect();
+
+        done.push(v.clone());
+
+        let slot = if i < n { i } else { missing.remove(0) };
+
+        basis[slot] = Some(v);
+    }
+
+    let columns: Vec<Vec<t!()>> = basis.into_iter().map(|v| v.unwrap()).collect();
+
+    (0..n).map(|row| columns.iter().map(|column| column[row]).collect()).collect()
+}
+
+/*
+        diag(A, B) on qubits first (the one choosing between them) and the ones under it as
+    W, Rz rotations on qubit first multiplexed over the rest, then V
+*/
+fn demultiplex(a: &Vec<Vec<t!()>>, b: &Vec<Vec<t!()>>, first: usize) -> Vec<Instruction> {
+    let (values, v) = unitary_eigen(&matrix_multiply(a, &dagger(b)));
+
+    let roots: Vec<t!()> = values.iter().map(|z| {
+        let angle = z[1].atan2(z[0]) / 2.0;
+
+        [angle.cos(), angle.sin()]
+    }).collect();
+
+    let d = (0..roots.len()).map(|i| (0..roots.len()).map(|j| if i == j { roots[i] } else { eq!(0) }).collect()).collect();
+    let w = matrix_multiply(&d, &matrix_multiply(&dagger(&v), b));
+
+    // diag(d, d*) = Rz(-2 arg d)
+    let angles: Vec<f64> = roots.iter().map(|z| -2.0 * z[1].atan2(z[0])).collect();
+    let controls: Vec<usize> = (first + 1..first + 1 + (a.len() as f64).log2() as usize).collect();
+
+    let mut circuit = shannon(&w, first + 1);
+
+    circuit.extend(multiplexed_rotation(&rz, &angles, &controls, first));
+    circuit.extend(shannon(&v, first + 1));
+
+    circuit
+}
+
+/*
+        rotation(angles[j]) on target when the controls (controls[0] most significant) read j.
+    Splitting on the first control, R(a) then CNOT R(b) CNOT gives R(a + b) when it's 0 and
+    R(a - b) when it's 1, since X R(θ) X = R(-θ) for Ry and Rz. The second half gets built
+    mirrored (CNOT R(b) CNOT R(a) works just as well), which puts the CNOTs the two halves
+    end and start with next to each other. All of them share the target so they commute,
+    and the pairs cancel down to the 2^k CNOTs a multiplexor over k qubits needs
+*/
+fn multiplexed_rotation(rotation: &dyn Fn(f64) -> Vec<Vec<t!()>>, angles: &[f64], controls: &[usize], target: usize) -> Vec<Instruction> {
+    let mut circuit = Vec::new();
+
+    if angles.iter().all(|angle| angle.abs() < 1e-15) { return circuit };
+
+    for instruction in multiplexor(rotation, angles, controls, target, false) {
+        let cnot = matches!(&instruction, Instruction::Gate(gate, _) if gate.len() > 2);
+        let run = circuit.iter().rev().take_while(|earlier| matches!(earlier, Instruction::Gate(gate, _) if gate.len() > 2)).count();
+
+        let partner = if cnot {
+            (circuit.len() - run..circuit.len()).find(|&j| match (&circuit[j], &instruction) {
+                (Instruction::Gate(a, i), Instruction::Gate(b, k)) => a == b && i == k,
+                _ => false
+            })
+        } else {
+            None
+        };
+
+        match partner {
+            Some(j) => { circuit.remove(j); },
+            None => circuit.push(instruction)
+        }
+    }
+
+    circuit
+}
+
+fn multiplexor(rotation: &dyn Fn(f64) -> Vec<Vec<t!()>>, angles: &[f64], controls: &[usize], target: usize, mirrored: bool) -> Vec<Instruction> {
+    let (control, rest) = match controls.split_first() {
+        None => return if angles[0].abs() < 1e-15 { vec![] } else { vec![Instruction::Gate(rotation(angles[0]), target)] },
+        Some((&control, rest)) => (control, rest)
+    };
+
+    let half = angles.len() / 2;
+    let sum: Vec<f64> = (0..half).map(|j| (angles[j] + angles[half + j]) / 2.0).collect();
+    let difference: Vec<f64> = (0..half).map(|j| (angles[j] - angles[half + j]) / 2.0).collect();
+
+    let (cnot, index) = embed_gate(&CNOT, &[control, target]);
+    let cnot = Instruction::Gate(cnot, index);
+
+    let mut circuit = Vec::new();
+
+    if mirrored {
+        circuit.push(cnot.clone());
+        circuit.extend(multiplexor(rotation, &difference, rest, target, false));
+        circuit.push(cnot);
+        circuit.extend(multiplexor(rotation, &sum, rest, target, true));
+    } else {
+        circuit.extend(multiplexor(rotation, &sum, rest, target, false));
+        circuit.push(cnot.clone());
+        circuit.extend(multiplexor(rotation, &difference, rest, target, true));
+        circuit.push(cnot);
+    }
+
+    circuit
+}
+
+/*
+        A circuit taking |0...0⟩ to the (normalized) state up to a global phase, following
+    Möttönen et al. Read backwards it disentangles the last qubit: every pair of amplitudes
+    differing only in that qubit, r0 e^iφ0 and r1 e^iφ1, is Rz(φ1 - φ0) Ry(2 atan2(r1, r0))
+    |0⟩ on it, times √(r0² + r1²) e^i(φ0 + φ1)/2 left on the qubits before it. So the circuit
+    prepares those amplitudes on one qubit less, then applies the Ry and Rz rotations
+    multiplexed over the qubits before. Rotations (and whole multiplexors) that come out as
+    zero are left out, which keeps real states free of Rz
+*/
+pub fn prepare_state(state: &Vec<t!()>) -> Vec<Instruction> {
+    let qubits = (state.len() as f64).log2() as usize;
+
+    if state.len() < 2 || 1 << qubits != state.len() { panic!("a state of length {} isn't made of qubits", state.len()) };
+
+    let size = length(state);
+
+    if size < 1e-12 { panic!("the zero vector isn't a state") };
+
+    let mut circuit = prepare(&state.iter().map(|z| eq!(z / #size)).collect(), qubits);
+
+    fuse_gates(&mut circuit);
+
+    circuit
+}
+
+fn prepare(amplitudes: &Vec<t!()>, qubits: usize) -> Vec<Instruction> {
+    if qubits == 0 { return vec![] };
+
+    let pairs: Vec<(t!(), t!())> = amplitudes.chunks(2).map(|pair| (pair[0], pair[1])).collect();
+    let magnitude = |z: t!()| (z[0].powf(2.0) + z[1].powf(2.0)).sqrt();
+    let argument = |z: t!()| z[1].atan2(z[0]);
+
+    let thetas: Vec<f64> = pairs.iter().map(|&(a, b)| 2.0 * magnitude(b).atan2(magnitude(a))).collect();
+    let omegas: Vec<f64> = pairs.iter().map(|&(a, b)| argument(b) - argument(a)).collect();
+
+    let remaining = pairs.iter().map(|&(a, b)| {
+        let (r, alpha) = (magnitude(a).hypot(magnitude(b)), (argument(a) + argument(b)) / 2.0);
+
+        [r * alpha.cos(), r * alpha.sin()]
+    }).collect();
+
+    let target = qubits - 1;
+    let controls: Vec<usize> = (0..target).collect();
+
+    let mut circuit = prepare(&remaining, qubits - 1);
+
+    circuit.extend(multiplexed_rotation(&ry, &thetas, &controls, target));
+    circuit.extend(multiplexed_rotation(&rz, &omegas, &controls, target));
+
+    circuit
+}
+
+fn matrix_vector(matrix: &Vec<Vec<t!()>>, vector: &Vec<t!()>) -> Vec<t!()> {
+    matrix.iter().map(|row| row.iter().zip(vector.iter()).fold(eq!(0), |accum, (a, b)| eq!(accum + a * b))).collect()
+}
+
+fn length(vector: &Vec<t!()>) -> f64 {
+    vector.iter().map(|z| z[0].powf(2.0) + z[1].powf(2.0)).sum::<f64>().sqrt()
+}
@@ -1,3 +1,7 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread::LocalKey;
+
 use ga_macros::*;
 use lazy_static::lazy_static;
 use colored::*;
@@ -37,6 +41,134 @@ lazy_static! {
         vec![eq!(0), eq!(0), eq!(0), eq!(1)],
         vec![eq!(0), eq!(0), eq!(1), eq!(0)]
     ];
+
+    pub static ref SGATE: Vec<Vec<t!()>> = vec![
+        vec![eq!(1), eq!(0)],
+        vec![eq!(0), eq!(i)]
+    ];
+
+    pub static ref SDAGGER: Vec<Vec<t!()>> = vec![
+        vec![eq!(1), eq!(0)],
+        vec![eq!(0), eq!(-i)]
+    ];
+
+    pub static ref TGATE: Vec<Vec<t!()>> = vec![
+        vec![eq!(1), eq!(0)],
+        vec![eq!(0), eq!((1 + i) / 2^0.5)]
+    ];
+
+    pub static ref TDAGGER: Vec<Vec<t!()>> = vec![
+        vec![eq!(1), eq!(0)],
+        vec![eq!(0), eq!((1 - i) / 2^0.5)]
+    ];
+
+    // √X
+    pub static ref SXGATE: Vec<Vec<t!()>> = vec![
+        vec![eq!((1 + i) / 2), eq!((1 - i) / 2)],
+        vec![eq!((1 - i) / 2), eq!((1 + i) / 2)]
+    ];
+
+    pub static ref CZ: Vec<Vec<t!()>> = vec![
+        vec![eq!(1), eq!(0), eq!(0), eq!(0)],
+        vec![eq!(0), eq!(1), eq!(0), eq!(0)],
+        vec![eq!(0), eq!(0), eq!(1), eq!(0)],
+        vec![eq!(0), eq!(0), eq!(0), eq!(-1)]
+    ];
+
+    pub static ref SWAP: Vec<Vec<t!()>> = vec![
+        vec![eq!(1), eq!(0), eq!(0), eq!(0)],
+        vec![eq!(0), eq!(0), eq!(1), eq!(0)],
+        vec![eq!(0), eq!(1), eq!(0), eq!(0)],
+        vec![eq!(0), eq!(0), eq!(0), eq!(1)]
+    ];
+}
+
+pub fn rx(theta: f64) -> Vec<Vec<t!()>> {
+    let (c, s) = ((theta / 2.0).cos(), (theta / 2.0).sin());
+
+    vec![
+        vec![[c, 0.0], [0.0, -s]],
+        vec![[0.0, -s], [c, 0.0]]
+    ]
+}
+
+pub fn ry(theta: f64) -> Vec<Vec<t!()>> {
+    let (c, s) = ((theta / 2.0).cos(), (theta / 2.0).sin());
+
+    vec![
+        vec![[c, 0.0], [-s, 0.0]],
+        vec![[s, 0.0], [c, 0.0]]
+    ]
+}
+
+pub fn rz(theta: f64) -> Vec<Vec<t!()>> {
+    let (c, s) = ((theta / 2.0).cos(), (theta / 2.0).sin());
+
+    vec![
+        vec![[c, -s], [0.0, 0.0]],
+        vec![[0.0, 0.0], [c, s]]
+    ]
+}
+
+// diag(1, e^iθ)
+pub fn phase(theta: f64) -> Vec<Vec<t!()>> {
+    vec![
+        vec![eq!(1), eq!(0)],
+        vec![eq!(0), [theta.cos(), theta.sin()]]
+    ]
+}
+
+// [[cos(θ/2), -e^iλ sin(θ/2)], [e^iφ sin(θ/2), e^i(φ+λ) cos(θ/2)]]
+pub fn u3(theta: f64, phi: f64, lambda: f64) -> Vec<Vec<t!()>> {
+    let (c, s) = ((theta / 2.0).cos(), (theta / 2.0).sin());
+
+    vec![
+        vec![[c, 0.0], [-s * lambda.cos(), -s * lambda.sin()]],
+        vec![[s * phi.cos(), s * phi.sin()], [c * (phi + lambda).cos(), c * (phi + lambda).sin()]]
+    ]
+}
+
+/*
+        Gates only ever act on a contiguous run of qubits, so a gate meant for arbitrary
+    qubits (qubits[0] being its most significant one) gets spread over every qubit from the
+    lowest to the highest of them, acting as the identity on the ones in between. Returns
+    the widened gate along with the index it should be placed at
+*/
+pub fn embed_gate(gate: &Vec<Vec<t!()>>, qubits: &[usize]) -> (Vec<Vec<t!()>>, usize) {
+    if 1 << qubits.len() != gate.len() { panic!("a gate of size {} can't act on {} qubits", gate.len(), qubits.len()) };
+
+    let start = *qubits.iter().min().expect("a gate needs at least one qubit");
+    let span = qubits.iter().max().unwrap() - start + 1;
+
+    if qubits.iter().enumerate().all(|(k, &q)| q == start + k) { return (gate.clone(), start) };
+
+    let positions: Vec<usize> = qubits.iter().map(|q| span - 1 - (q - start)).collect();
+    let mask: usize = positions.iter().map(|p| 1 << p).sum();
+    let gate_index = |i: usize| positions.iter().fold(0, |accum, p| (accum << 1) | ((i >> p) & 1));
+
+    let matrix = (0..(1 << span)).map(|row| {
+        (0..(1 << span)).map(|column| {
+            if row & !mask == column & !mask { gate[gate_index(row)][gate_index(column)] } else { eq!(0) }
+        }).collect()
+    }).collect();
+
+    (matrix, start)
+}
+
+// The gate acting on the qubits after ctrl_state.len() control qubits, only when each
+// control is in its ctrl_state (true for |1⟩, false for |0⟩)
+pub fn controlled_gate(gate: &Vec<Vec<t!()>>, ctrl_state: &[bool]) -> Vec<Vec<t!()>> {
+    let size = gate.len();
+    let active = ctrl_state.iter().fold(0, |accum, &state| (accum << 1) | state as usize);
+    let mut matrix = identity(size << ctrl_state.len());
+
+    for row in 0..size {
+        for column in 0..size {
+            matrix[active * size + row][active * size + column] = gate[row][column];
+        }
+    }
+
+    matrix
 }
 
 pub fn tensor_product_vector(vectors: Vec<&Vec<t!()>>) -> Vec<t!()> {
@@ -206,25 +338,200 @@ pub fn print_matrix_without_breaklines(matrix: &Vec<Vec<[f64; 2]>>) -> String {
 pub fn pass_gate(tensor: &mut Vec<t!()>, gate: &Vec<Vec<t!()>>) {
     if tensor.len() != gate.len() { panic!("tensor and gate should be the same length") };
 
-    let mut result: Vec<t!()> = vec![eq!(0); tensor.len()];
+    let input = tensor.clone();
 
-    for i in 0..tensor.len() {
-        for j in 0..tensor.len() {
-            result[i] = eq!(result[i] + tensor[j] * gate[i][j]);
+    for_each_block(tensor, |offset, block| {
+        for (k, z) in block.iter_mut().enumerate() {
+            let row = &gate[offset + k];
+            let mut result = eq!(0);
+
+            for j in 0..input.len() {
+                result = eq!(result + input[j] * row[j]);
+            }
+
+            *z = result;
         }
+    });
+}
+
+/*
+        Applies a gate to the qubits starting at tensor_order_index without building the
+    full tensor product with the identity. Every amplitude only mixes with the ones that
+    differ from it in the gate's qubits, which sit `low` indices apart. Single qubit gates
+    update each pair in place, wider ones read from a copy of the tensor
+*/
+pub fn apply_gate(tensor: &mut Vec<t!()>, gate: &Vec<Vec<t!()>>, tensor_order_index: usize) {
+    let low = tensor.len() / (gate.len() << tensor_order_index);
+
+    if low == 0 { panic!("gate doesn't fit in the tensor at index {}", tensor_order_index) };
+
+    if gate.len() == 2 {
+        let (a, b, c, d) = (gate[0][0], gate[0][1], gate[1][0], gate[1][1]);
+
+        for_each_pair(tensor, low, |zero, one| {
+            let (x, y) = (*zero, *one);
+
+            *zero = eq!(a * x + b * y);
+            *one = eq!(c * x + d * y);
+        });
+
+        return;
     }
 
-    *tensor = result;
+    let block_len = low * gate.len();
+    let input = tensor.clone();
+
+    for_each_block(tensor, |offset, block| {
+        for (k, z) in block.iter_mut().enumerate() {
+            let i = offset + k;
+            let base = i / block_len * block_len + i % low;
+            let row = &gate[i % block_len / low];
+            let mut result = eq!(0);
+
+            for m in 0..gate.len() {
+                result = eq!(result + input[base + m * low] * row[m]);
+            }
+
+            *z = result;
+        }
+    });
 }
 
 pub fn norm(tensor: &mut Vec<t!()>) {
     let len = length(tensor);
 
-    for z in tensor.iter_mut() {
-        *z = eq!(z / #len);
+    for_each_block(tensor, |_, block| {
+        for z in block.iter_mut() {
+            *z = eq!(z / #len);
+        }
+    });
+}
+
+static PARALLEL_THRESHOLD: AtomicUsize = AtomicUsize::new(1 << 16);
+
+// Tensors with at least this many amplitudes get their kernels split across threads
+pub fn set_parallel_threshold(amplitudes: usize) {
+    PARALLEL_THRESHOLD.store(amplitudes, Ordering::Relaxed);
+}
+
+pub fn parallel_threshold() -> usize {
+    KERNEL_THRESHOLD.with(|threshold| threshold.get()).unwrap_or_else(|| PARALLEL_THRESHOLD.load(Ordering::Relaxed))
+}
+
+thread_local! {
+    // How many threads kernels started from this thread can split into, None for every core
+    static KERNEL_THREADS: Cell<Option<usize>> = const { Cell::new(None) };
+
+    // Overrides the parallel threshold for kernels started from this thread
+    static KERNEL_THRESHOLD: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+// Runs f with the kernels it starts split across at most threads threads
+pub fn with_kernel_threads<T>(threads: usize, f: impl FnOnce() -> T) -> T {
+    scoped(&KERNEL_THREADS, threads.max(1), f)
+}
+
+// Runs f with its own parallel threshold, leaving every other thread's alone
+pub fn with_parallel_threshold<T>(amplitudes: usize, f: impl FnOnce() -> T) -> T {
+    scoped(&KERNEL_THRESHOLD, amplitudes, f)
+}
+
+pub fn kernel_threads() -> usize {
+    KERNEL_THREADS.with(|budget| budget.get()).unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1))
+}
+
+// Puts a thread's kernel setting back the way it was once dropped, so a panic in f can't leave it changed
+struct Restore(&'static LocalKey<Cell<Option<usize>>>, Option<usize>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        self.0.with(|setting| setting.set(self.1));
     }
 }
 
+fn scoped<T>(setting: &'static LocalKey<Cell<Option<usize>>>, value: usize, f: impl FnOnce() -> T) -> T {
+    let _restore = Restore(setting, setting.with(|current| current.replace(Some(value))));
+
+    f()
+}
+
+/*
+        Hands contiguous blocks of amplitudes (along with the index each block starts at)
+    to f, one block per thread once the tensor is above the parallel threshold, with as many
+    threads as kernel_threads allows. f has to compute every amplitude the same way regardless
+    of the block it's in, which keeps the parallel results bit-identical to the serial ones
+*/
+pub fn for_each_block<F>(tensor: &mut [t!()], f: F) where F: Fn(usize, &mut [t!()]) + Sync {
+    // Small tensors don't ask for the budget at all, finding out how many cores there are
+    // costs more than the kernel
+    let threads = if tensor.len() < parallel_threshold() { 1 } else { kernel_threads() };
+
+    if threads == 1 {
+        f(0, tensor);
+
+        return;
+    }
+
+    let block_len = tensor.len().div_ceil(threads);
+
+    std::thread::scope(|scope| {
+        for (i, block) in tensor.chunks_mut(block_len).enumerate() {
+            let f = &f;
+
+            scope.spawn(move || f(i * block_len, block));
+        }
+    });
+}
+
+/*
+        Hands f both amplitudes of every pair that only differs in the bit worth low, with
+    the same threads for_each_block would use. Each thread gets an even share of the pairs,
+    either a run of whole blocks of 2 * low amplitudes or matching parts of the two halves of
+    one block
+*/
+fn for_each_pair<F>(tensor: &mut [t!()], low: usize, f: F) where F: Fn(&mut t!(), &mut t!()) + Sync {
+    let threads = if tensor.len() < parallel_threshold() { 1 } else { kernel_threads() };
+    let pairs = |amplitudes: &mut [t!()]| {
+        for block in amplitudes.chunks_mut(2 * low) {
+            let (zeros, ones) = block.split_at_mut(low);
+
+            zeros.iter_mut().zip(ones.iter_mut()).for_each(|(zero, one)| f(zero, one));
+        }
+    };
+
+    if threads == 1 {
+        pairs(tensor);
+
+        return;
+    }
+
+    let share = tensor.len().div_ceil(threads);
+
+    std::thread::scope(|scope| {
+        if share >= 2 * low {
+            for amplitudes in tensor.chunks_mut(share.div_ceil(2 * low) * 2 * low) {
+                let pairs = &pairs;
+
+                scope.spawn(move || pairs(amplitudes));
+            }
+
+            return;
+        }
+
+        let part = (share / 2).max(1);
+
+        for block in tensor.chunks_mut(2 * low) {
+            let (zeros, ones) = block.split_at_mut(low);
+
+            for (zeros, ones) in zeros.chunks_mut(part).zip(ones.chunks_mut(part)) {
+                let f = &f;
+
+                scope.spawn(move || zeros.iter_mut().zip(ones.iter_mut()).for_each(|(zero, one)| f(zero, one)));
+            }
+        }
+    });
+}
+
 fn length(tensor: &Vec<t!()>) -> f64 {
     let mut len = 0.0;
 
@@ -247,4 +554,81 @@ pub fn complement(v: t!()) -> t!() {
 */  
 pub fn is_from_one(tensor_order_index: usize, loc_in_tensor: usize, tensor_len: usize) -> bool {
     loc_in_tensor % (tensor_len / ((2 as f64).powf(tensor_order_index as f64) as usize)) >= tensor_len / ((2 as f64).powf(tensor_order_index as f64 + 1.0) as usize)
-}
\ No newline at end of file
+}
+
+// ⟨a|b⟩
+pub fn inner_product(a: &Vec<t!()>, b: &Vec<t!()>) -> t!() {
+    if a.len() != b.len() { panic!("tensors should be the same length") };
+
+    let mut result = eq!(0);
+
+    for i in 0..a.len() {
+        let a_ = complement(a[i]);
+
+        result = eq!(result + a_ * b[i]);
+    }
+
+    result
+}
+
+pub fn matrix_multiply(m1: &Vec<Vec<t!()>>, m2: &Vec<Vec<t!()>>) -> Vec<Vec<t!()>> {
+    if m1[0].len() != m2.len() { panic!("matrix dimensions don't line up") };
+
+    let mut result: Vec<Vec<t!()>> = vec![vec![eq!(0); m2[0].len()]; m1.len()];
+
+    for i in 0..m1.len() {
+        for k in 0..m2.len() {
+            for j in 0..m2[0].len() {
+                result[i][j] = eq!(result[i][j] + m1[i][k] * m2[k][j]);
+            }
+        }
+    }
+
+    result
+}
+
+// Conjugate transpose
+pub fn dagger(matrix: &Vec<Vec<t!()>>) -> Vec<Vec<t!()>> {
+    (0..matrix[0].len()).map(|j| {
+        (0..matrix.len()).map(|i| complement(matrix[i][j])).collect()
+    }).collect()
+}
+
+pub fn identity(size: usize) -> Vec<Vec<t!()>> {
+    (0..size).map(|i| {
+        (0..size).map(|j| if i == j { eq!(1) } else { eq!(0) }).collect()
+    }).collect()
+}
+
+// |ψ⟩⟨ψ|
+pub fn density_matrix(tensor: &Vec<t!()>) -> Vec<Vec<t!()>> {
+    tensor.iter().map(|a| {
+        tensor.iter().map(|b| {
+            let b_ = complement(*b);
+
+            eq!(a * b_)
+        }).collect()
+    }).collect()
+}
+
+// Traces out every qubit except the one at tensor_order_index, leaving its 2x2 density matrix
+pub fn reduced_density_matrix(tensor: &Vec<t!()>, tensor_order_index: usize) -> Vec<Vec<t!()>> {
+    let mut result: Vec<Vec<t!()>> = vec![vec![eq!(0); 2]; 2];
+    let stride = tensor.len() / (2 << tensor_order_index);
+
+    for i in 0..tensor.len() {
+        if is_from_one(tensor_order_index, i, tensor.len()) { continue };
+
+        let zero = tensor[i];
+        let one = tensor[i + stride];
+        let zero_ = complement(zero);
+        let one_ = complement(one);
+
+        result[0][0] = eq!(result[0][0] + zero * zero_);
+        result[0][1] = eq!(result[0][1] + zero * one_);
+        result[1][0] = eq!(result[1][0] + one * zero_);
+        result[1][1] = eq!(result[1][1] + one * one_);
+    }
+
+    result
+}
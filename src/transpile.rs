@@ -0,0 +1,274 @@
+use std::f64::consts::PI;
+
+use ga_macros::*;
+
+use crate::*;
+
+// How close to zero an amplitude (or a rotation angle) has to be to get left out
+const TRANSPILE_TOLERANCE: f64 = 1e-10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NativeGate {
+    X,
+    SX,
+    H,
+    RX,
+    RY,
+    RZ,
+    U3,
+    CNOT,
+    CZ
+}
+
+/*
+        The gates a transpiled experiment is allowed to use. Rotations are parameterized, the
+    rest are fixed, and the set has to be able to build any single qubit gate: U3 on its
+    own, two of RX, RY and RZ, RZ with SX, or RZ or RX with H (which swaps the two axes).
+    RY with H doesn't do, both being real they never make anything complex
+*/
+#[derive(Clone, Debug)]
+pub struct Target {
+    gates: Vec<NativeGate>
+}
+
+impl Target {
+    pub fn new(gates: &[NativeGate]) -> Self {
+        let target = Target { gates: gates.to_vec() };
+
+        if target.single_qubit_basis().is_none() {
+            panic!("can't make every single qubit gate out of {:?}", gates)
+        }
+
+        target
+    }
+
+    // RZ, SX, X and CNOT
+    pub fn ibm() -> Self {
+        Target::new(&[NativeGate::RZ, NativeGate::SX, NativeGate::X, NativeGate::CNOT])
+    }
+
+    // RX, RY and CZ
+    pub fn rotations_cz() -> Self {
+        Target::new(&[NativeGate::RX, NativeGate::RY, NativeGate::CZ])
+    }
+
+    pub fn gates(&self) -> &Vec<NativeGate> {
+        &self.gates
+    }
+
+    pub fn supports(&self, gate: NativeGate) -> bool {
+        self.gates.contains(&gate)
+    }
+
+    // Preferred in this order when the target has more than one way of doing it
+    fn single_qubit_basis(&self) -> Option<(NativeGate, NativeGate)> {
+        [
+            (NativeGate::U3, NativeGate::U3),
+            (NativeGate::RZ, NativeGate::RY),
+            (NativeGate::RZ, NativeGate::RX),
+            (NativeGate::RX, NativeGate::RY),
+            (NativeGate::RZ, NativeGate::SX),
+            (NativeGate::RZ, NativeGate::H),
+            (NativeGate::RX, NativeGate::H)
+        ].into_iter().find(|(a, b)| self.supports(*a) && self.supports(*b))
+    }
+}
+
+/*
+        Rewrites an experiment to only use the target's gates. Angled measurements are
+    already rotations around a plain measurement by the time an experiment exists, so only
+    gates need replacing: single qubit ones become Euler rotations, two qubit ones go
+    through kak_decompose and wider ones through synthesize_unitary. Gates acting trivially
+    on some of their qubits are shrunk down first, which is what keeps CNOTs between distant
+    qubits cheap. Afterwards fuse_commuting_gates cleans up and whatever single qubit gates
+    it merged get rewritten into the target's gates again. fuse_gates isn't run on top of
+    that, fuse_commuting_gates already merges everything it would
+*/
+pub fn transpile(experiment: &Experiment, target: &Target) -> Experiment {
+    let mut instructions = experiment.instructions.clone();
+
+    lower_gates(&mut instructions, target);
+    fuse_commuting_gates(&mut instructions);
+    lower_gates(&mut instructions, target);
+
+    Experiment { qubits: experiment.qubits, state: experiment.state.clone(), instructions }
+}
+
+fn lower_gates(instructions: &mut Vec<Instruction>, target: &Target) {
+    let mut output = Vec::new();
+
+    for mut instruction in instructions.drain(..) {
+        match instruction {
+            Instruction::Gate(gate, index) => {
+                let qubits: Vec<usize> = (index..index + span(&gate)).collect();
+
+                output.extend(lower(&gate, &qubits, target));
+            },
+            Instruction::Dependent(ref mut body, _) | Instruction::Repeat(ref mut body, _) | Instruction::While(_, ref mut body, _) | Instruction::RepeatUntil(ref mut body, _) => {
+                if let Instruction::Circuit(circuit, _) = &mut **body { lower_gates(circuit, target) };
+
+                output.push(instruction);
+            },
+            _ => output.push(instruction)
+        }
+    }
+
+    *instructions = output;
+}
+
+// gate acting on qubits (qubits[0] being its most significant one) in the target's gates
+fn lower(gate: &Vec<Vec<t!()>>, qubits: &[usize], target: &Target) -> Vec<Instruction> {
+    let active = active_qubits(gate);
+
+    if active.len() < qubits.len() {
+        let qubits: Vec<usize> = active.iter().map(|&q| qubits[q]).collect();
+
+        return lower(&restrict(gate, &active), &qubits, target);
+    }
+
+    match qubits.len() {
+        0 => vec![],
+        1 => single_qubit(gate, qubits[0], target),
+        2 => two_qubit(gate, qubits[0], qubits[1], target),
+        _ => synthesize_unitary(gate).circuit.into_iter().flat_map(|instruction| match instruction {
+            Instruction::Gate(gate, index) => {
+                let on: Vec<usize> = (index..index + span(&gate)).map(|q| qubits[q]).collect();
+
+                lower(&gate, &on, target)
+            },
+            _ => unreachable!()
+        }).collect()
+    }
+}
+
+fn single_qubit(gate: &Vec<Vec<t!()>>, qubit: usize, target: &Target) -> Vec<Instruction> {
+    let fixed = [(NativeGate::X, &*PAULIX), (NativeGate::SX, &*SXGATE), (NativeGate::H, &*HADAMARD)];
+
+    if let Some((_, matrix)) = fixed.iter().find(|(native, matrix)| target.supports(*native) && unitary_equiv_up_to_phase(gate, matrix, TRANSPILE_TOLERANCE)) {
+        return vec![Instruction::Gate((*matrix).clone(), qubit)];
+    }
+
+    let rotation = |native: NativeGate, angle: f64| -> Option<Instruction> {
+        let angle = wrap(angle);
+
+        if angle.abs() < TRANSPILE_TOLERANCE { return None };
+
+        let matrix = match native {
+            NativeGate::RX => rx(angle),
+            NativeGate::RY => ry(angle),
+            _ => rz(angle)
+        };
+
+        Some(Instruction::Gate(matrix, qubit))
+    };
+
+    let (basis, axis) = target.single_qubit_basis().unwrap();
+
+    // H swaps X and Z and flips Y, so the ZYZ angles of HUH are the XYX angles of U and its
+    // ZXZ angles are the XZX ones
+    let swapped = || matrix_multiply(&HADAMARD, &matrix_multiply(gate, &HADAMARD));
+    let angles = match (basis, axis) {
+        (NativeGate::RX, NativeGate::H) => euler_angles(&swapped(), EulerBasis::ZXZ),
+        (NativeGate::RX, _) => euler_angles(&swapped(), EulerBasis::ZYZ),
+        (_, NativeGate::RX) | (_, NativeGate::H) => euler_angles(gate, EulerBasis::ZXZ),
+        _ => euler_angles(gate, EulerBasis::ZYZ)
+    };
+
+    let (theta, phi, lambda) = (angles.theta, angles.phi, angles.lambda);
+
+    // Rotations listed in the order they're applied
+    let rotations = match (basis, axis) {
+        (NativeGate::U3, _) => vec![Some(Instruction::Gate(u3(theta, phi, lambda), qubit))],
+        (NativeGate::RX, _) if theta.abs() < TRANSPILE_TOLERANCE => vec![rotation(NativeGate::RX, phi + lambda)],
+        (_, _) if theta.abs() < TRANSPILE_TOLERANCE => vec![rotation(NativeGate::RZ, phi + lambda)],
+        (basis, NativeGate::H) => {
+            // The middle rotation is around the other axis, HRz(θ)H = Rx(θ) and the other way round
+            let h = Some(Instruction::Gate(HADAMARD.clone(), qubit));
+
+            vec![rotation(basis, lambda), h.clone(), rotation(basis, theta), h, rotation(basis, phi)]
+        },
+        (NativeGate::RX, _) => vec![rotation(NativeGate::RX, lambda), rotation(NativeGate::RY, -theta), rotation(NativeGate::RX, phi)],
+        (_, NativeGate::SX) => {
+            // U3(θ, φ, λ) = Rz(φ + π) SX Rz(θ + π) SX Rz(λ) up to phase
+            let sx = Some(Instruction::Gate(SXGATE.clone(), qubit));
+
+            vec![rotation(NativeGate::RZ, lambda), sx.clone(), rotation(NativeGate::RZ, theta + PI), sx, rotation(NativeGate::RZ, phi + PI)]
+        },
+        (_, axis) => vec![rotation(NativeGate::RZ, lambda), rotation(axis, theta), rotation(NativeGate::RZ, phi)]
+    };
+
+    rotations.into_iter().flatten().collect()
+}
+
+// Through kak_decompose, with each of its CNOTs turned into a CZ between Hadamards if need be
+fn two_qubit(gate: &Vec<Vec<t!()>>, first: usize, second: usize, target: &Target) -> Vec<Instruction> {
+    if !target.supports(NativeGate::CNOT) && !target.supports(NativeGate::CZ) {
+        panic!("the target has no two qubit gate to build {} out of", describe_gate(gate))
+    }
+
+    for native in [NativeGate::CNOT, NativeGate::CZ] {
+        let matrix = if native == NativeGate::CNOT { &*CNOT } else { &*CZ };
+
+        if target.supports(native) && unitary_equiv_up_to_phase(gate, matrix, TRANSPILE_TOLERANCE) {
+            return vec![entangler(matrix, first, second)];
+        }
+    }
+
+    kak_decompose(gate).circuit.into_iter().flat_map(|instruction| match instruction {
+        Instruction::Gate(matrix, index) if matrix.len() == 2 => single_qubit(&matrix, [first, second][index], target),
+        _ if target.supports(NativeGate::CNOT) => vec![entangler(&CNOT, first, second)],
+        _ => {
+            let mut gates = single_qubit(&HADAMARD, second, target);
+
+            gates.push(entangler(&CZ, first, second));
+            gates.extend(single_qubit(&HADAMARD, second, target));
+
+            gates
+        }
+    }).collect()
+}
+
+fn entangler(gate: &Vec<Vec<t!()>>, first: usize, second: usize) -> Instruction {
+    let (matrix, index) = embed_gate(gate, &[first, second]);
+
+    Instruction::Gate(matrix, index)
+}
+
+// The qubits (counted from the gate's most significant one) the gate doesn't act on as the identity
+pub(crate) fn active_qubits(gate: &Vec<Vec<t!()>>) -> Vec<usize> {
+    let qubits = span(gate);
+    let zero = |z: t!()| z[0].abs() < TRANSPILE_TOLERANCE && z[1].abs() < TRANSPILE_TOLERANCE;
+
+    (0..qubits).filter(|q| {
+        let mask = 1 << (qubits - 1 - q);
+
+        !(0..gate.len()).all(|row| (0..gate.len()).all(|column| {
+            if (row ^ column) & mask != 0 { return zero(gate[row][column]) };
+
+            let (z, w) = (gate[row][column], gate[row ^ mask][column ^ mask]);
+
+            zero(eq!(z - w))
+        }))
+    }).collect()
+}
+
+// The part of gate acting on the active qubits, with every other qubit left at |0⟩
+pub(crate) fn restrict(gate: &Vec<Vec<t!()>>, active: &[usize]) -> Vec<Vec<t!()>> {
+    let qubits = span(gate);
+    let spread = |i: usize| active.iter().enumerate().fold(0, |accum, (k, q)| {
+        accum | (((i >> (active.len() - 1 - k)) & 1) << (qubits - 1 - q))
+    });
+
+    (0..1 << active.len()).map(|row| (0..1 << active.len()).map(|column| gate[spread(row)][spread(column)]).collect()).collect()
+}
+
+pub(crate) fn span(gate: &Vec<Vec<t!()>>) -> usize {
+    (gate.len() as f64).log2() as usize
+}
+
+// Into (-π, π], a rotation by θ - 2π only differs by a global phase
+fn wrap(angle: f64) -> f64 {
+    let wrapped = angle.rem_euclid(2.0 * PI);
+
+    if wrapped > PI { wrapped - 2.0 * PI } else { wrapped }
+}
@@ -0,0 +1,86 @@
+extern crate quantum_sim;
+use std::collections::BTreeMap;
+
+use quantum_sim::*;
+use rand::{SeedableRng, rngs::StdRng};
+
+#[test]
+fn terminal_measurements_are_batched() {
+    let angled = Experiment::new(vec![
+        ZERO.clone(),
+        ZERO.clone(),
+    ], vec![
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Gate(CNOT.clone(), 0),
+        Instruction::MeasureAtAngle((45.0 as f64).to_radians(), 0, true),
+        Instruction::MeasureAtAngle((90.0 as f64).to_radians(), 1, true),
+    ]);
+
+    assert!(angled.is_batchable());
+
+    let counts = angled.average_out_with(10000, 1, Some(7));
+
+    assert_eq!(counts.shots(), 10000);
+    assert_eq!(counts.qubits(), &vec![0, 1]);
+    assert!(counts.total_variation_test(&angled.exact_distribution()).passes(0.99));
+
+    let remeasured = Experiment::new(vec![
+        ZERO.clone(),
+        ZERO.clone(),
+    ], vec![
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Gate(CNOT.clone(), 0),
+        Instruction::MeasureAtAngle((45.0 as f64).to_radians(), 0, true),
+        Instruction::MeasureAtAngle((90.0 as f64).to_radians(), 0, true),
+    ]);
+
+    assert!(remeasured.is_batchable());
+    assert!(remeasured.average_out_with(10000, 1, Some(8)).total_variation_test(&remeasured.exact_distribution()).passes(0.99));
+}
+
+#[test]
+fn dependent_measurements_are_not_batched() {
+    let teleport = Experiment::new(vec![
+        ONE.clone(),
+        ZERO.clone(),
+        ZERO.clone()
+    ], vec![
+        Instruction::Gate(HADAMARD.clone(), 1),
+        Instruction::Gate(CNOT.clone(), 1),
+        Instruction::Gate(CNOT.clone(), 0),
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Measure(1, false),
+        Instruction::Measure(0, false),
+        Instruction::Dependent(Box::new(Instruction::Gate(PAULIX.clone(), 2)), 0),
+        Instruction::Dependent(Box::new(Instruction::Gate(PAULIZ.clone(), 2)), 1),
+        Instruction::Measure(2, true)
+    ]);
+
+    assert!(!teleport.is_batchable());
+    assert_eq!(teleport.average_out(100).count("1"), 100);
+
+    let measure_then_entangle = Experiment::new(vec![
+        ZERO.clone(),
+        ZERO.clone()
+    ], vec![
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Measure(0, true),
+        Instruction::Gate(CNOT.clone(), 0),
+        Instruction::Measure(1, true)
+    ]);
+
+    assert!(!measure_then_entangle.is_batchable());
+}
+
+#[test]
+fn sample_table() {
+    let distribution: BTreeMap<String, f64> = vec![
+        (String::from("0"), 0.25),
+        (String::from("1"), 0.75)
+    ].into_iter().collect();
+
+    let counts = SampleTable::new(&distribution).sample(vec![0], 20000, &mut StdRng::seed_from_u64(7));
+
+    assert_eq!(counts.shots(), 20000);
+    assert!(counts.total_variation_test(&distribution).passes(0.99));
+}
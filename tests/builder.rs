@@ -0,0 +1,138 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+fn teleport() -> Vec<Instruction> {
+    CircuitBuilder::new(3)
+        .h(1)
+        .cx(1, 2)
+        .cx(0, 1)
+        .h(0)
+        .measure_hidden(1)
+        .measure_hidden(0)
+        .when(0, |c| c.x(2))
+        .when(1, |c| c.z(2))
+        .build()
+}
+
+#[test]
+fn builds_the_same_gates_by_hand() {
+    let built = CircuitBuilder::new(2).h(0).cx(0, 1).measure(0).measure(1).experiment();
+
+    let by_hand = Experiment::new(vec![ZERO.clone(), ZERO.clone()], vec![
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Gate(CNOT.clone(), 0),
+        Instruction::Measure(0, true),
+        Instruction::Measure(1, true)
+    ]);
+
+    assert_eq!(built.exact_distribution(), by_hand.exact_distribution());
+}
+
+#[test]
+fn sub_circuits_and_conditionals() {
+    let mut qubit = ONE.clone();
+    pass_gate(&mut qubit, &HADAMARD);
+
+    // Teleports qubit 1 onto qubit 3 after an X on qubit 0 that shouldn't interfere
+    let experiment = CircuitBuilder::new(4)
+        .x(0)
+        .sub(&teleport(), 1)
+        .experiment_from(vec![ZERO.clone(), qubit.clone(), ZERO.clone(), ZERO.clone()]);
+
+    for _ in 0..10 {
+        let state = experiment.run().0;
+
+        assert!(trace_distance(&reduced_density_matrix(&state, 3), &density_matrix(&qubit)) < 1e-9);
+        assert!(trace_distance(&reduced_density_matrix(&state, 0), &density_matrix(&ONE)) < 1e-9);
+    }
+}
+
+#[test]
+fn conditional_bodies_run_in_order() {
+    // X then H leaves |−⟩ (so the final H gives 1), H then X would leave |+⟩
+    let experiment = CircuitBuilder::new(2)
+        .x(0)
+        .measure(0)
+        .when(0, |c| c.x(1).h(1))
+        .h(1)
+        .measure(1)
+        .experiment();
+
+    let distribution = experiment.exact_distribution();
+
+    assert_eq!(distribution.len(), 1);
+    assert!((distribution["11"] - 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn sub_circuits_number_their_own_measurements() {
+    // Measures a qubit and flips it back if it was 1, which leaves it at 0 either way
+    let reset = CircuitBuilder::new(1).measure_hidden(0).when(0, |c| c.x(0)).build();
+
+    let experiment = CircuitBuilder::new(2)
+        .x(0)
+        .sub(&reset, 0)
+        .sub(&reset, 1)
+        .measure(0)
+        .measure(1)
+        .experiment();
+
+    assert!((experiment.exact_distribution()["00"] - 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn nested_conditions_see_earlier_measurements() {
+    // Qubit 2 only flips when both measurements before the bodies came out as 1
+    let experiment = CircuitBuilder::new(3)
+        .x(0)
+        .h(1)
+        .measure_hidden(0)
+        .measure(1)
+        .when(0, |c| c.when(1, |c| c.x(2)))
+        .measure(2)
+        .experiment();
+
+    let distribution = experiment.exact_distribution();
+
+    assert!((distribution["11"] - 0.5).abs() < 1e-12 && (distribution["00"] - 0.5).abs() < 1e-12);
+
+    // The body's own measurement is #1, after the one that let the body run
+    let experiment = CircuitBuilder::new(2)
+        .x(0)
+        .measure_hidden(0)
+        .when(0, |c| {
+            assert_eq!(c.measurements(), 1);
+
+            c.x(1).measure_hidden(1).when(1, |c| c.x(0))
+        })
+        .measure(0)
+        .experiment();
+
+    assert!((experiment.exact_distribution()["0"] - 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn reversed_and_distant_controls() {
+    // cx(2, 0) flips qubit 0 when qubit 2 is set, leaving qubit 1 alone
+    let experiment = CircuitBuilder::new(3)
+        .x(2)
+        .cx(2, 0)
+        .measure(0)
+        .measure(1)
+        .measure(2)
+        .experiment();
+
+    assert!((experiment.exact_distribution()["101"] - 1.0).abs() < 1e-12);
+}
+
+#[test]
+#[should_panic(expected = "out of range")]
+fn out_of_range_qubit() {
+    CircuitBuilder::new(2).h(0).cx(1, 2);
+}
+
+#[test]
+#[should_panic(expected = "hasn't happened yet")]
+fn conditional_on_future_measurement() {
+    CircuitBuilder::new(2).measure(0).when(1, |c| c.x(1));
+}
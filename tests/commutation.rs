@@ -0,0 +1,70 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+fn optimized(circuit: &Vec<Instruction>, qubits: usize) -> (usize, Vec<Instruction>) {
+    let mut experiment = Experiment::new(vec![ZERO.clone(); qubits], circuit.clone());
+    let removed = experiment.optimize_commuting();
+
+    assert!(check_equivalence(circuit, experiment.instructions(), qubits, 0.999).is_equivalent());
+
+    (removed, experiment.instructions().clone())
+}
+
+#[test]
+fn gates_slide_through_cnots() {
+    // Z commutes with a control and X with a target
+    assert_eq!(optimized(&CircuitBuilder::new(2).z(0).cx(0, 1).z(0).build(), 2).0, 2);
+    assert_eq!(optimized(&CircuitBuilder::new(2).x(1).cx(0, 1).x(1).build(), 2).0, 2);
+
+    // but not the other way around
+    assert_eq!(optimized(&CircuitBuilder::new(2).x(0).cx(0, 1).x(0).build(), 2).0, 0);
+    assert_eq!(optimized(&CircuitBuilder::new(2).z(1).cx(0, 1).z(1).build(), 2).0, 0);
+}
+
+#[test]
+fn rotations_merge_across_diagonal_gates() {
+    let (removed, instructions) = optimized(&CircuitBuilder::new(2).rz(0.3, 0).cz(0, 1).t(1).rz(0.4, 0).build(), 2);
+
+    assert_eq!(removed, 1);
+    assert_eq!(instructions.len(), 3);
+}
+
+#[test]
+fn redundant_corrections_after_deferred_measurement() {
+    // The corrections a Dependent would have applied, turned into controlled gates, with an
+    // extra pair of Xs that cancel through the CNOT and a pair of Zs through the CZ
+    let circuit = CircuitBuilder::new(3)
+        .h(1)
+        .cx(1, 2)
+        .cx(0, 1)
+        .h(0)
+        .x(2)
+        .cx(1, 2)
+        .x(2)
+        .z(2)
+        .cz(0, 2)
+        .z(2)
+        .build();
+
+    let (removed, _) = optimized(&circuit, 3);
+
+    assert_eq!(removed, 4);
+
+    let mut peephole = Experiment::new(vec![ZERO.clone(); 3], circuit);
+
+    // which the peephole pass can't see, it only fuses the adjacent X and Z
+    assert_eq!(peephole.optimize(), 1);
+}
+
+#[test]
+fn diagonal_gates_pass_measurements() {
+    let mut experiment = CircuitBuilder::new(1).h(0).t(0).measure(0).tdg(0).h(0).measure(0).experiment();
+    let before = experiment.exact_distribution();
+
+    assert_eq!(experiment.optimize_commuting(), 2);
+    assert_eq!(experiment.exact_distribution().len(), before.len());
+
+    for (outcome, probability) in experiment.exact_distribution() {
+        assert!((before[&outcome] - probability).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,62 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+use ga_macros::*;
+
+#[test]
+fn teleported_qubit_matches_input() {
+    let qubit = vec![eq!(1/2), eq!(i * (3/4)^0.5)];
+
+    let experiment = Experiment::new(vec![
+        qubit.clone(),
+        ZERO.clone(),
+        ZERO.clone()
+    ], vec![
+        Instruction::Gate(HADAMARD.clone(), 1),
+        Instruction::Gate(CNOT.clone(), 1),
+        Instruction::Gate(CNOT.clone(), 0),
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Measure(1, false),
+        Instruction::Measure(0, false),
+        Instruction::Dependent(Box::new(Instruction::Gate(PAULIX.clone(), 2)), 0),
+        Instruction::Dependent(Box::new(Instruction::Gate(PAULIZ.clone(), 2)), 1),
+    ]);
+
+    for _ in 0..20 {
+        let teleported = reduced_density_matrix(&experiment.run().0, 2);
+
+        assert!(trace_distance(&teleported, &density_matrix(&qubit)) < 1e-9);
+    }
+}
+
+#[test]
+fn state_comparisons() {
+    let mut plus = ZERO.clone();
+    pass_gate(&mut plus, &HADAMARD);
+
+    assert!(fidelity(&ZERO, &ONE).abs() < 1e-12);
+    assert!((fidelity(&ZERO, &plus) - 0.5).abs() < 1e-12);
+    assert!((trace_distance(&density_matrix(&ZERO), &density_matrix(&ONE)) - 1.0).abs() < 1e-12);
+    assert!((trace_distance(&density_matrix(&ZERO), &density_matrix(&plus)) - 0.5f64.powf(0.5)).abs() < 1e-12);
+
+    let phased: Vec<[f64; 2]> = plus.iter().map(|z| eq!(i * z)).collect();
+
+    assert!(equal_up_to_global_phase(&plus, &phased, 1e-12));
+    assert!(!equal_up_to_global_phase(&plus, &ZERO, 1e-12));
+}
+
+#[test]
+fn operator_comparisons() {
+    let hzh = matrix_multiply(&HADAMARD, &matrix_multiply(&PAULIZ, &HADAMARD));
+    let minus_y: Vec<Vec<[f64; 2]>> = PAULIY.iter().map(|row| row.iter().map(|z| eq!(-1 * z)).collect()).collect();
+
+    assert!(unitary_equiv_up_to_phase(&hzh, &PAULIX, 1e-12));
+    assert!(unitary_equiv_up_to_phase(&minus_y, &PAULIY, 1e-12));
+    assert!(!unitary_equiv_up_to_phase(&PAULIX, &PAULIZ, 1e-12));
+    assert!(unitary_equiv_up_to_phase(&matrix_multiply(&HADAMARD, &dagger(&HADAMARD)), &IDENTITY, 1e-12));
+}
+
+#[test]
+#[should_panic(expected = "zero vector")]
+fn fidelity_with_nothing() {
+    fidelity(&ZERO, &vec![[0.0, 0.0]; 2]);
+}
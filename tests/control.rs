@@ -0,0 +1,141 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+use rand::{SeedableRng, rngs::StdRng};
+
+#[test]
+fn conditions() {
+    let results = [true, false, true];
+
+    assert!(Condition::All(vec![Condition::One(0), Condition::Zero(1)]).holds(&results));
+    assert!(Condition::Any(vec![Condition::Zero(0), Condition::One(2)]).holds(&results));
+    assert!(!Condition::Not(Box::new(Condition::One(2))).holds(&results));
+
+    // Measurements that haven't happened read as 0
+    assert!(Condition::Zero(5).holds(&results));
+    assert_eq!(Condition::Any(vec![Condition::One(0), Condition::Zero(3)]).span(), 4);
+}
+
+#[test]
+fn repeat_until_success() {
+    // Keeps putting the qubit in |+⟩ and measuring it until it lands on |1⟩
+    let experiment = CircuitBuilder::new(1)
+        .repeat_until(|c| c.h(0).measure_hidden(0), Condition::One(0))
+        .measure(0)
+        .experiment();
+
+    let counts = experiment.average_out_with(4000, 2, Some(3));
+
+    assert_eq!(counts.count("1"), 4000);
+    assert_eq!(counts.iterations()[&0].values().sum::<usize>(), 4000);
+    assert!((counts.mean_iterations(0).unwrap() - 2.0).abs() < 0.15);
+    assert!(counts.gave_up().is_empty());
+
+    let shot = experiment.shot_with(&mut StdRng::seed_from_u64(3));
+
+    assert_eq!(shot.iterations.len(), 1);
+    assert!(shot.iterations[0].0 == 0 && shot.iterations[0].1 >= 1);
+    assert_eq!(shot.measurements, vec![Measurement(0, true, None)]);
+}
+
+#[test]
+fn bounded_while_loop() {
+    // Retries at most 3 times, then the measurement after the loop is #0 again and the
+    // conditional after it has to see that one
+    let experiment = CircuitBuilder::new(2)
+        .while_loop(Condition::Zero(0), 3, |c| c.h(0).measure_hidden(0))
+        .measure(0)
+        .when(0, |c| c.x(1))
+        .measure(1)
+        .experiment();
+
+    let counts = experiment.average_out_with(8000, 4, Some(11));
+
+    assert_eq!(counts.count("01") + counts.count("10"), 0);
+    assert!((counts.probability("11") - 0.875).abs() < 0.02);
+
+    let iterations = &counts.iterations()[&0];
+
+    assert_eq!(iterations.keys().cloned().collect::<Vec<usize>>(), vec![1, 2, 3]);
+    assert!((iterations[&3] as f64 / 8000.0 - 0.25).abs() < 0.02);
+    assert!(counts.to_string().contains("Loop 0 iterations: [ 1 => "));
+}
+
+#[test]
+fn displayed_measurements_in_loops() {
+    // Every try shows up in the shot, but the measurement after the loop is still #0
+    let experiment = CircuitBuilder::new(2)
+        .repeat_until(|c| c.h(0).measure(0), Condition::One(0))
+        .measure_hidden(0)
+        .when(0, |c| c.x(1))
+        .measure(1)
+        .experiment();
+
+    for seed in 0..20 {
+        let shot = experiment.shot_with(&mut StdRng::seed_from_u64(seed));
+        let tries = shot.iterations[0].1;
+
+        assert_eq!(shot.measurements.len(), tries + 1);
+        assert!(shot.measurements[..tries].iter().enumerate().all(|(k, m)| m.0 == 0 && m.1 == (k + 1 == tries)));
+        assert_eq!(shot.measurements[tries], Measurement(1, true, None));
+    }
+}
+
+#[test]
+fn iterations_per_loop() {
+    // The first loop takes two tries on average, the second always runs out its 3 iterations
+    let experiment = CircuitBuilder::new(2)
+        .repeat_until(|c| c.h(0).measure_hidden(0), Condition::One(0))
+        .while_loop(Condition::Zero(0), 3, |c| c.measure_hidden(1))
+        .measure(0)
+        .experiment();
+
+    let counts = experiment.average_out_with(4000, 2, Some(5));
+
+    assert_eq!(counts.iterations().keys().cloned().collect::<Vec<usize>>(), vec![0, 1]);
+    assert!((counts.mean_iterations(0).unwrap() - 2.0).abs() < 0.15);
+    assert_eq!(counts.iterations()[&1].get(&3), Some(&4000));
+    assert_eq!(counts.mean_iterations(2), None);
+
+    let shot = experiment.shot_with(&mut StdRng::seed_from_u64(5));
+
+    assert_eq!(shot.iterations.iter().map(|&(number, _)| number).collect::<Vec<usize>>(), vec![0, 1]);
+}
+
+#[test]
+fn repeat_until_gives_up() {
+    // |0⟩ never measures as 1, so the loop stops at its limit instead of running forever
+    let experiment = CircuitBuilder::new(1)
+        .repeat_until(|c| c.measure_hidden(0), Condition::One(0))
+        .measure(0)
+        .experiment();
+
+    let shot = experiment.shot_with(&mut StdRng::seed_from_u64(1));
+
+    assert_eq!(shot.gave_up, vec![0]);
+    assert_eq!(shot.iterations, vec![(0, 1 << 20)]);
+    assert_eq!(shot.measurements, vec![Measurement(0, false, None)]);
+
+    let mut counts = Counts::new();
+
+    counts.record(&shot.measurements);
+    counts.record_iterations(&shot.iterations, &shot.gave_up);
+
+    assert_eq!(counts.gave_up().get(&0), Some(&1));
+    assert!(counts.to_string().contains("gave up 1 times"));
+}
+
+#[test]
+#[should_panic(expected = "never happens")]
+fn condition_on_a_missing_measurement() {
+    CircuitBuilder::new(1).repeat_until(|c| c.h(0).measure(0), Condition::One(1));
+}
+
+#[test]
+#[should_panic(expected = "loops can only be run shot by shot")]
+fn loops_have_no_exact_distribution() {
+    CircuitBuilder::new(1)
+        .repeat_until(|c| c.h(0).measure_hidden(0), Condition::One(0))
+        .measure(0)
+        .experiment()
+        .exact_distribution();
+}
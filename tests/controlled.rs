@@ -0,0 +1,86 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+fn truth_table(prepare: &[usize], gate: &Instruction) -> String {
+    let mut instructions: Vec<Instruction> = prepare.iter().map(|&q| Instruction::Gate(PAULIX.clone(), q)).collect();
+
+    instructions.push(gate.clone());
+    instructions.extend((0..3).map(|q| Instruction::Measure(q, true)));
+
+    let distribution = Experiment::new(vec![ZERO.clone(); 3], instructions).exact_distribution();
+
+    assert_eq!(distribution.len(), 1);
+
+    distribution.keys().next().unwrap().clone()
+}
+
+#[test]
+fn toffoli_with_mixed_controls() {
+    let toffoli = Instruction::Gate(PAULIX.clone(), 2).controlled(vec![0, 1], vec![true, true]);
+
+    assert_eq!(truth_table(&[0, 1], &toffoli), "111");
+    assert_eq!(truth_table(&[0], &toffoli), "100");
+
+    // Fires when qubit 2 is |1⟩ and qubit 0 is |0⟩, with the target in between them
+    let mixed = Instruction::Gate(PAULIX.clone(), 1).controlled(vec![2, 0], vec![true, false]);
+
+    assert_eq!(truth_table(&[2], &mixed), "011");
+    assert_eq!(truth_table(&[0, 2], &mixed), "101");
+    assert_eq!(truth_table(&[], &mixed), "000");
+}
+
+#[test]
+fn controlled_powers_for_phase_estimation() {
+    // Two bits of phase estimation on |1⟩ with U = T·T = S (phase 1/4), using controlled
+    // sub circuits for U and U^2
+    let u = Instruction::Circuit(vec![Instruction::Gate(TGATE.clone(), 0), Instruction::Gate(TGATE.clone(), 0)], 2);
+    let u_squared = Instruction::Circuit(vec![u.clone(), u.clone()], 0);
+
+    let experiment = Experiment::new(vec![ZERO.clone(), ZERO.clone(), ONE.clone()], vec![
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Gate(HADAMARD.clone(), 1),
+        u_squared.controlled(vec![0], vec![true]),
+        u.controlled(vec![1], vec![true]),
+        // Inverse QFT on two qubits
+        Instruction::Gate(SWAP.clone(), 0),
+        Instruction::Gate(HADAMARD.clone(), 1),
+        Instruction::Gate(SDAGGER.clone(), 0).controlled(vec![1], vec![true]),
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Measure(1, true),
+        Instruction::Measure(0, true)
+    ]);
+
+    // Phase 1/4 = 0.01 in binary, read least significant bit first
+    assert!((experiment.exact_distribution()["10"] - 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn builder_controls_sub_circuits() {
+    let bell = CircuitBuilder::new(2).h(0).cx(0, 1).build();
+
+    let off = CircuitBuilder::new(3).controlled(&bell, 1, &[0], &[true]).measure(1).measure(2).experiment();
+    let on = CircuitBuilder::new(3).x(0).controlled(&bell, 1, &[0], &[true]).measure(1).measure(2).experiment();
+
+    assert!((off.exact_distribution()["00"] - 1.0).abs() < 1e-12);
+    assert!((on.exact_distribution()["11"] - 0.5).abs() < 1e-12);
+}
+
+#[test]
+#[should_panic(expected = "can't control a gate acting on it")]
+fn control_on_a_target() {
+    Instruction::Gate(CNOT.clone(), 0).controlled(vec![1], vec![true]);
+}
+
+#[test]
+#[should_panic(expected = "measurements can't be controlled")]
+fn controlled_measurement() {
+    Instruction::Circuit(vec![Instruction::Measure(0, true)], 1).controlled(vec![0], vec![true]);
+}
+
+#[test]
+#[should_panic(expected = "loops run on measurements")]
+fn controlled_loop() {
+    let body = Instruction::Circuit(vec![Instruction::Gate(HADAMARD.clone(), 0), Instruction::Measure(0, true)], 1);
+
+    Instruction::RepeatUntil(Box::new(body), Condition::One(0)).controlled(vec![0], vec![true]);
+}
@@ -0,0 +1,108 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+fn bell_pair() -> Experiment {
+    Experiment::new(vec![
+        ZERO.clone(),
+        ZERO.clone()
+    ], vec![
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Gate(CNOT.clone(), 0),
+        Instruction::Measure(0, true),
+        Instruction::Measure(1, true)
+    ])
+}
+
+#[test]
+fn bell_counts() {
+    let counts = bell_pair().average_out(1000);
+
+    assert_eq!(counts.shots(), 1000);
+    assert_eq!(counts.count("00") + counts.count("11"), 1000);
+    assert_eq!(counts.count("01") + counts.count("10"), 0);
+    assert_eq!(counts.qubits(), &vec![0, 1]);
+    assert_eq!(counts.counts().keys().cloned().collect::<Vec<String>>(), vec!["00", "11"]);
+
+    let first = counts.marginal(&[0]);
+    let given_one = counts.conditional(&[(0, true)]);
+
+    assert_eq!(first.count("0"), counts.count("00"));
+    assert_eq!(given_one.shots(), counts.count("11"));
+    assert_eq!(given_one.count("1"), given_one.shots());
+}
+
+#[test]
+fn merging_and_ranking() {
+    let mut a = Counts::new();
+    a.add(String::from("00"), 3);
+    a.add(String::from("01"), 1);
+
+    let mut b = Counts::new();
+    b.add(String::from("01"), 4);
+    b.add(String::from("11"), 2);
+
+    let merged = Counts::merged(vec![a, b]);
+
+    assert_eq!(merged.shots(), 10);
+    assert_eq!(merged.count("01"), 5);
+    assert!((merged.probability("00") - 0.3).abs() < 1e-12);
+    assert_eq!(
+        merged.most_frequent(2),
+        vec![(String::from("01"), 5), (String::from("00"), 3)]
+    );
+}
+
+#[test]
+fn pretty_output() {
+    let mut counts = Counts::new();
+
+    counts.record(&vec![Measurement(0, false, None), Measurement(1, true, None)]);
+    counts.record(&vec![Measurement(0, true, None), Measurement(1, true, None)]);
+
+    assert_eq!(
+        counts.to_string(),
+        "Measurements: [ 0: false, 1:  true ] => 50.00%\nMeasurements: [ 0:  true, 1:  true ] => 50.00%"
+    );
+}
+
+// Measures qubit 1 when qubit 0 comes out as 1 and qubit 2 otherwise
+fn either_qubit(flip_one: bool) -> Experiment {
+    let mut instructions = vec![
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Measure(0, false),
+        Instruction::Gate(PAULIX.clone(), 0),
+        Instruction::Measure(0, false)
+    ];
+
+    if flip_one { instructions.push(Instruction::Gate(PAULIX.clone(), 1)) };
+
+    instructions.push(Instruction::Dependent(Box::new(Instruction::Measure(1, true)), 0));
+    instructions.push(Instruction::Dependent(Box::new(Instruction::Measure(2, true)), 1));
+
+    Experiment::new(vec![ZERO.clone(); 3], instructions)
+}
+
+#[test]
+fn conditional_measurements_keep_their_qubits() {
+    let counts = either_qubit(true).average_out_with(200, 1, Some(3));
+
+    assert_eq!(counts.qubits_of("1"), Some(&vec![1]));
+    assert_eq!(counts.qubits_of("0"), Some(&vec![2]));
+    assert_eq!(counts.to_string(), format!(
+        "Measurements: [ 2: false ] => {:.2}%\nMeasurements: [ 1:  true ] => {:.2}%",
+        100.0 * counts.probability("0"),
+        100.0 * counts.probability("1")
+    ));
+}
+
+#[test]
+#[should_panic(expected = "came from measuring qubits")]
+fn colliding_bitstrings() {
+    either_qubit(false).average_out_with(200, 1, Some(3));
+}
+
+#[test]
+#[should_panic(expected = "out of range")]
+fn marginal_out_of_range() {
+    bell_pair().average_out_with(10, 1, Some(3)).marginal(&[0, 2]);
+}
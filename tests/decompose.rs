@@ -0,0 +1,59 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+fn assert_close(a: &Vec<Vec<[f64; 2]>>, b: &Vec<Vec<[f64; 2]>>) {
+    for (row_a, row_b) in a.iter().zip(b.iter()) {
+        for (x, y) in row_a.iter().zip(row_b.iter()) {
+            assert!((x[0] - y[0]).abs() < 1e-12 && (x[1] - y[1]).abs() < 1e-12, "{} != {}", print_matrix(a), print_matrix(b));
+        }
+    }
+}
+
+fn with_phase(gate: &Vec<Vec<[f64; 2]>>, phase: f64) -> Vec<Vec<[f64; 2]>> {
+    matrix_multiply(&vec![vec![[phase.cos(), phase.sin()], [0.0, 0.0]], vec![[0.0, 0.0], [phase.cos(), phase.sin()]]], gate)
+}
+
+#[test]
+fn every_basis_rebuilds_the_gate() {
+    let gates = vec![
+        with_phase(&matrix_multiply(&HADAMARD, &matrix_multiply(&TGATE, &rx(0.7))), 0.4),
+        with_phase(&u3(2.1, -0.3, 1.9), -2.5),
+        TGATE.clone(),
+        PAULIX.clone(),
+        PAULIY.clone(),
+        ry(std::f64::consts::PI)
+    ];
+
+    for gate in gates.iter() {
+        for basis in [EulerBasis::ZYZ, EulerBasis::ZXZ, EulerBasis::U3] {
+            let angles = euler_angles(gate, basis);
+
+            assert_eq!(angles.basis, basis);
+            assert_close(&angles.matrix(), gate);
+        }
+    }
+}
+
+#[test]
+fn u3_angles_of_a_u3_gate() {
+    let angles = euler_angles(&u3(0.8, 0.5, -1.1), EulerBasis::U3);
+
+    assert!((angles.theta - 0.8).abs() < 1e-12);
+    assert!((angles.phi - 0.5).abs() < 1e-12);
+    assert!((angles.lambda + 1.1).abs() < 1e-12);
+    assert!(angles.phase.abs() < 1e-12);
+}
+
+#[test]
+fn identifies_standard_gates() {
+    assert_eq!(identify_gate(&with_phase(&HADAMARD, 0.3)), Some("H"));
+    assert_eq!(identify_gate(&rz(std::f64::consts::FRAC_PI_2)), Some("S"));
+    assert_eq!(identify_gate(&rz(-std::f64::consts::FRAC_PI_4)), Some("T†"));
+    assert_eq!(identify_gate(&rx(std::f64::consts::PI)), Some("X"));
+    assert_eq!(identify_gate(&embed_gate(&CNOT, &[1, 0]).0), None);
+    assert_eq!(identify_gate(&CZ), Some("CZ"));
+    assert_eq!(identify_gate(&rx(0.3)), None);
+
+    assert_eq!(format!("{:?}", Instruction::Gate(HADAMARD.clone(), 1)), "{Gate: H @ 1}");
+    assert_eq!(format!("{:?}", Instruction::Gate(u3(0.5, 0.25, -1.0), 0)), "{Gate: U3(0.500, 0.250, -1.000) @ 0}");
+}
@@ -0,0 +1,89 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+use rand::{SeedableRng, rngs::StdRng};
+
+#[test]
+fn hand_optimized_circuits() {
+    // H X H is Z, and two CNOTs cancel
+    let reference = CircuitBuilder::new(3).h(1).x(1).h(1).cx(0, 2).cx(0, 2).t(0).t(0).build();
+    let optimized = CircuitBuilder::new(3).z(1).s(0).build();
+
+    assert!(check_equivalence(&reference, &optimized, 3, 0.999).is_equivalent());
+
+    // Y = iXZ, so they only differ by a global phase
+    let y = CircuitBuilder::new(1).y(0).build();
+    let xz = CircuitBuilder::new(1).z(0).x(0).build();
+
+    assert!(check_equivalence(&y, &xz, 1, 0.999).is_equivalent());
+}
+
+#[test]
+fn reports_a_distinguishing_input() {
+    let cx = CircuitBuilder::new(2).cx(0, 1).build();
+    let cz = CircuitBuilder::new(2).cz(0, 1).build();
+
+    match check_equivalence(&cx, &cz, 2, 0.999) {
+        Equivalence::Different { input, fidelity } => {
+            assert_eq!(input, "10");
+            assert!(fidelity < 1e-12);
+        },
+        other => panic!("CNOT and CZ aren't equivalent, got {:?}", other)
+    }
+
+    // Z and the identity agree on every basis state up to that state's own phase
+    let z = CircuitBuilder::new(1).z(0).build();
+
+    assert_eq!(check_equivalence(&z, &vec![], 1, 0.999), Equivalence::Different { input: "1".to_string(), fidelity: 1.0 });
+}
+
+#[test]
+fn fingerprinting_large_circuits() {
+    let qubits = 10;
+    let mut reference = CircuitBuilder::new(qubits);
+    let mut swapped = CircuitBuilder::new(qubits);
+
+    for q in 0..qubits - 1 {
+        reference = reference.h(q).cx(q, q + 1).rz(0.1 * q as f64, q + 1);
+        swapped = swapped.h(q).cx(q, q + 1).rz(0.1 * q as f64, q + 1);
+    }
+
+    let (reference, swapped) = (reference.build(), swapped.swap(3, 7).swap(7, 3).build());
+
+    let mut rng = StdRng::seed_from_u64(11);
+
+    assert!(check_equivalence_with(&reference, &swapped, qubits, 0.9999, &mut rng).is_equivalent());
+
+    // An X on the last qubit halfway through, which every input shows
+    let mut broken = reference.clone();
+    broken.insert(reference.len() / 2, Instruction::Gate(PAULIX.clone(), 9));
+
+    match check_equivalence_with(&reference, &broken, qubits, 0.9999, &mut rng) {
+        Equivalence::Different { input, fidelity: reported } => {
+            // The basis state it points at really does come out differently
+            let k = usize::from_str_radix(&input, 2).unwrap();
+            let mut u = vec![[0.0, 0.0]; 1 << qubits];
+            u[k] = [1.0, 0.0];
+
+            let mut v = u.clone();
+
+            Experiment::new(vec![ZERO.clone(); qubits], reference.clone()).evolve(&mut u);
+            Experiment::new(vec![ZERO.clone(); qubits], broken).evolve(&mut v);
+
+            assert_eq!(input.len(), qubits);
+            assert!((fidelity(&u, &v) - reported).abs() < 1e-12);
+            assert!(reported < 1.0 - 1e-9);
+        },
+        other => panic!("a missing X should be caught, got {:?}", other)
+    }
+
+    // A phase flip on |1...1⟩ alone, before anything else, is traced back to that input
+    let mut flip = identity(1 << qubits);
+    flip[(1 << qubits) - 1][(1 << qubits) - 1] = [-1.0, 0.0];
+
+    let flipped = [vec![Instruction::Gate(flip, 0)], reference.clone()].concat();
+
+    match check_equivalence_with(&reference, &flipped, qubits, 0.9999, &mut rng) {
+        Equivalence::Different { input, .. } => assert_eq!(input, "1111111111"),
+        other => panic!("the phase flip should be caught, got {:?}", other)
+    }
+}
@@ -1,26 +1,44 @@
 extern crate quantum_sim;
 
+use std::collections::BTreeMap;
+
 use quantum_sim::*;
 use lazy_static::lazy_static;
-use rand::Rng;
+use rand::{Rng, SeedableRng, rngs::StdRng};
 
 lazy_static! {
-    static ref ENTANGLE: Vec<Instruction> = vec![
-        Instruction::Gate(HADAMARD.clone(), 0),
-        Instruction::Gate(CNOT.clone(), 0),
-    ];
-
-    static ref TELEPORT: Vec<Instruction> = vec![
-        Instruction::Circuit(ENTANGLE.clone(), 1),
-        Instruction::Gate(CNOT.clone(), 0),
-        Instruction::Gate(HADAMARD.clone(), 0),
-        Instruction::Measure(1, false),
-        Instruction::Measure(0, false),
-        Instruction::Dependent(Box::new(Instruction::Gate(PAULIX.clone(), 2)), 0),
-        Instruction::Dependent(Box::new(Instruction::Gate(PAULIZ.clone(), 2)), 1),
-    ];
+    static ref ENTANGLE: Vec<Instruction> = circuit! {
+        qubits 2;
+        h 0;
+        cx 0 1;
+    };
+
+    static ref TELEPORT: Vec<Instruction> = circuit! {
+        qubits 3;
+        sub(ENTANGLE) 1;
+        cx 0 1;
+        h 0;
+        m0 = measure_hidden 1;
+        m1 = measure_hidden 0;
+        if m0 { x 2 }
+        if m1 { z 2 }
+    };
 }
 
+// Shots drawn from the exact distribution with a fixed seed, so the checks can't flake
+fn seeded_counts(distribution: &BTreeMap<String, f64>, shots: usize, seed: u64) -> Counts {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut counts = Counts::new();
+
+    for _ in 0..shots {
+        let mut r = rng.gen::<f64>();
+        let (outcome, _) = distribution.iter().find(|(_, &p)| { r -= p; r < 0.0 }).or(distribution.iter().last()).unwrap();
+
+        counts.add(outcome.clone(), 1);
+    }
+
+    counts
+}
 
 #[cfg(test)]
 mod experiment {
@@ -41,7 +59,18 @@ mod experiment {
             ]
         );
 
-        println!("{}", &experiment.average_out_pretty(10000));
+        let distribution = experiment.exact_distribution();
+        let counts = seeded_counts(&distribution, 10000, 7);
+
+        println!("{}", counts);
+
+        assert!(counts.total_variation_test(&distribution).passes(0.99));
+
+        for outcome in ["00", "11"] {
+            let (low, high) = counts.interval(outcome, 0.99, Interval::Wilson);
+
+            assert!(low <= 0.5 && 0.5 <= high);
+        }
     }
 
     #[test]
@@ -75,13 +104,8 @@ mod experiment {
             ("Constant-1", Instruction::Circuit(vec![
                 Instruction::Gate(PAULIX.clone(), 1)
             ], 0)),
-            ("Identity", Instruction::Circuit(vec![
-                Instruction::Gate(CNOT.clone(), 0)
-            ], 0)),
-            ("Negation", Instruction::Circuit(vec![
-                Instruction::Gate(CNOT.clone(), 0),
-                Instruction::Gate(PAULIX.clone(), 1)
-            ], 0)),
+            ("Identity", Instruction::Gate(PAULIX.clone(), 1).controlled(vec![0], vec![true])),
+            ("Negation", Instruction::Gate(PAULIX.clone(), 1).controlled(vec![0], vec![false])),
         ];
 
         for circuit in circuits.into_iter() {
@@ -138,6 +162,23 @@ mod experiment {
         println!("{}", &experiment.average_out_pretty(100));
     }
 
+    #[test]
+    fn dependent_bodies_run_in_order() {
+        // X then CNOT sets both qubits, the other way round the CNOT would find its control at 0
+        let experiment = Experiment::new(vec![ONE.clone(), ZERO.clone(), ZERO.clone()], vec![
+            Instruction::Measure(0, false),
+            Instruction::Dependent(Box::new(Instruction::Circuit(vec![
+                Instruction::Gate(PAULIX.clone(), 1),
+                Instruction::Gate(CNOT.clone(), 1)
+            ], 0)), 0),
+            Instruction::Measure(1, true),
+            Instruction::Measure(2, true)
+        ]);
+
+        assert!((experiment.exact_distribution()["11"] - 1.0).abs() < 1e-12);
+        assert_eq!(bitstring(&experiment.run().1), "11");
+    }
+
     #[test]
     fn teleport_with_circuit() {
         let mut qubit = ONE.clone();
@@ -0,0 +1,55 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+#[test]
+fn uncomputing_entanglement() {
+    let entangle = circuit! {
+        qubits 2;
+        h 0;
+        cx 0 1;
+    };
+
+    let experiment = Experiment::new(vec![ZERO.clone(); 3], circuit! {
+        qubits 3;
+        sub(entangle) 1;
+        inverse(entangle) 1;
+        measure 1;
+        measure 2;
+    });
+
+    assert!((experiment.exact_distribution()["00"] - 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn circuit_followed_by_its_inverse_is_the_identity() {
+    let circuit = CircuitBuilder::new(3)
+        .rx(0.3, 0)
+        .t(1)
+        .cx(0, 2)
+        .ry(1.1, 2)
+        .swap(1, 2)
+        .rz(-0.7, 1)
+        .build();
+
+    let mut qubits = vec![ZERO.clone(), ONE.clone(), ZERO.clone()];
+    pass_gate(&mut qubits[0], &HADAMARD);
+
+    let state = tensor_product_vector(qubits.iter().collect());
+
+    let forward = Instruction::Circuit(circuit, 0);
+    let experiment = Experiment::new(qubits.clone(), vec![forward.clone(), forward.inverse()]);
+
+    assert!(fidelity(&experiment.run().0, &state) > 1.0 - 1e-12);
+}
+
+#[test]
+#[should_panic(expected = "measurements aren't reversible")]
+fn measurements_have_no_inverse() {
+    inverse_circuit(&CircuitBuilder::new(2).h(0).measure(0).build());
+}
+
+#[test]
+#[should_panic(expected = "depending on measurement #0")]
+fn dependents_have_no_inverse() {
+    Instruction::Dependent(Box::new(Instruction::Gate(PAULIX.clone(), 1)), 0).inverse();
+}
@@ -0,0 +1,84 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+use std::f64::consts::FRAC_PI_4;
+
+fn assert_rebuilds(gate: &Vec<Vec<[f64; 2]>>) -> TwoQubitDecomposition {
+    let decomposition = kak_decompose(gate);
+    let rebuilt = circuit_unitary(&decomposition.circuit, 2);
+
+    assert!(unitary_equiv_up_to_phase(&rebuilt, gate, 1e-8), "{} != {}", print_matrix(&rebuilt), print_matrix(gate));
+
+    let cnots = decomposition.circuit.iter().filter(|instruction| matches!(instruction, Instruction::Gate(g, _) if g.len() == 4)).count();
+
+    assert_eq!(cnots, decomposition.cnots);
+
+    decomposition
+}
+
+#[test]
+fn standard_gates() {
+    assert_eq!(assert_rebuilds(&CNOT).cnots, 1);
+    assert_eq!(assert_rebuilds(&CZ).cnots, 1);
+    assert_eq!(assert_rebuilds(&tensor_product_matrix(vec![&HADAMARD, &TGATE])).cnots, 0);
+
+    let swap = assert_rebuilds(&SWAP);
+
+    assert_eq!(swap.cnots, 3);
+
+    for coordinate in swap.weyl {
+        assert!((coordinate - FRAC_PI_4).abs() < 1e-9);
+    }
+}
+
+#[test]
+fn two_cnot_gates() {
+    // A controlled rotation by θ is (θ/4, 0, 0), one CNOT isn't enough but two are
+    let controlled_ry = Instruction::Gate(ry(0.7), 1).controlled(vec![0], vec![true]);
+    let gate = circuit_unitary(&vec![controlled_ry], 2);
+
+    let decomposition = assert_rebuilds(&gate);
+
+    assert_eq!(decomposition.cnots, 2);
+    assert!((decomposition.weyl[0] - 0.175).abs() < 1e-9);
+}
+
+#[test]
+fn random_gates_stay_in_the_weyl_chamber() {
+    for seed in 0..20 {
+        let circuit = CircuitBuilder::new(2)
+            .u3(0.3 * seed as f64, 1.1, -0.4 * seed as f64, 0)
+            .cx(0, 1)
+            .ry(0.9 + seed as f64, 1)
+            .rz(0.2 * seed as f64, 0)
+            .cx(1, 0)
+            .rx(1.7 - seed as f64, 0)
+            .cx(0, 1)
+            .u3(2.1, -0.5 * seed as f64, 0.8, 1)
+            .build();
+
+        let decomposition = assert_rebuilds(&circuit_unitary(&circuit, 2));
+        let [a, b, c] = decomposition.weyl;
+
+        assert!(FRAC_PI_4 + 1e-9 >= a && a + 1e-9 >= b && b + 1e-9 >= c.abs(), "{:?}", decomposition.weyl);
+        assert!(decomposition.cnots <= 3);
+    }
+}
+
+#[test]
+fn coordinates_ignore_local_gates() {
+    let core = circuit_unitary(&CircuitBuilder::new(2).cx(0, 1).rz(0.6, 1).cx(0, 1).build(), 2);
+    let dressed = circuit_unitary(&CircuitBuilder::new(2).h(0).t(1).cx(0, 1).rz(0.6, 1).cx(0, 1).ry(0.3, 0).s(1).build(), 2);
+
+    let (plain, local) = (weyl_coordinates(&core), weyl_coordinates(&dressed));
+
+    for k in 0..3 {
+        assert!((plain[k] - local[k]).abs() < 1e-9, "{:?} != {:?}", plain, local);
+    }
+}
+
+#[test]
+#[should_panic(expected = "kak_decompose needs a two qubit gate")]
+fn only_two_qubit_gates() {
+    kak_decompose(&HADAMARD);
+}
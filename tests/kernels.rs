@@ -0,0 +1,95 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+use rand::{SeedableRng, rngs::StdRng};
+
+fn layered_circuit(qubits: usize) -> Experiment {
+    let mut instructions = Vec::new();
+
+    for layer in 0..3 {
+        for q in 0..qubits {
+            instructions.push(Instruction::Gate(if (q + layer) % 2 == 0 { HADAMARD.clone() } else { PAULIY.clone() }, q));
+        }
+
+        for q in (layer % 2..qubits - 1).step_by(2) {
+            instructions.push(Instruction::Gate(CNOT.clone(), q));
+        }
+
+        instructions.push(Instruction::MeasureAtAngle((30.0 * layer as f64).to_radians(), layer, true));
+    }
+
+    Experiment::new(vec![ZERO.clone(); qubits], instructions)
+}
+
+#[test]
+fn local_gates_match_full_tensor_products() {
+    let mut tensor = vec![ZERO.clone(), ONE.clone(), ZERO.clone(), ONE.clone()];
+    tensor.iter_mut().for_each(|qubit| pass_gate(qubit, &HADAMARD));
+
+    let state = tensor_product_vector(tensor.iter().collect());
+
+    for (gate, index) in [(&*PAULIY, 2), (&*CNOT, 1), (&*HADAMARD, 0), (&*CNOT, 2)] {
+        let mut matrices: Vec<&Vec<Vec<[f64; 2]>>> = vec![&*IDENTITY; 4 - (gate.len() / 2 - 1)];
+        matrices[index] = gate;
+
+        let mut full = state.clone();
+        let mut local = state.clone();
+
+        pass_gate(&mut full, &tensor_product_matrix(matrices));
+        apply_gate(&mut local, gate, index);
+
+        assert_eq!(full, local);
+    }
+}
+
+#[test]
+fn parallel_kernels_are_bit_identical() {
+    let experiment = layered_circuit(10);
+
+    let serial = with_kernel_threads(1, || experiment.run_with(&mut StdRng::seed_from_u64(7)));
+
+    // 4 threads whatever the machine has, splitting 2 amplitudes apart as well as 512
+    let parallel = with_kernel_threads(4, || with_parallel_threshold(4, || experiment.run_with(&mut StdRng::seed_from_u64(7))));
+
+    assert_eq!(parallel_threshold(), 1 << 16);
+    assert_eq!(serial.0, parallel.0);
+    assert_eq!(serial.1, parallel.1);
+}
+
+#[test]
+fn kernel_thread_budgets() {
+    let experiment = layered_circuit(8);
+    let everything = kernel_threads();
+
+    let (budget, limited) = with_kernel_threads(1, || (kernel_threads(), experiment.run_with(&mut StdRng::seed_from_u64(3))));
+
+    assert_eq!(budget, 1);
+    assert_eq!(kernel_threads(), everything);
+
+    // A panicking closure still gives the budget back
+    assert!(std::panic::catch_unwind(|| with_kernel_threads(3, || panic!("in the kernels"))).is_err());
+    assert_eq!(kernel_threads(), everything);
+    assert_eq!(limited, experiment.run_with(&mut StdRng::seed_from_u64(3)));
+
+    // Shot workers split the budget between them, which doesn't change seeded counts
+    assert_eq!(experiment.average_out_with(3000, 1, Some(5)), experiment.average_out_with(3000, 3, Some(5)));
+}
+
+#[test]
+fn large_ghz_state() {
+    let qubits = 16;
+    let mut instructions = vec![Instruction::Gate(HADAMARD.clone(), 0)];
+
+    for q in 0..qubits - 1 {
+        instructions.push(Instruction::Gate(CNOT.clone(), q));
+    }
+
+    for q in 0..qubits {
+        instructions.push(Instruction::Measure(q, true));
+    }
+
+    let distribution = Experiment::new(vec![ZERO.clone(); qubits], instructions).exact_distribution();
+
+    assert_eq!(distribution.len(), 2);
+    assert!((distribution[&"0".repeat(qubits)] - 0.5).abs() < 1e-12);
+    assert!((distribution[&"1".repeat(qubits)] - 0.5).abs() < 1e-12);
+}
@@ -0,0 +1,76 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+#[test]
+fn matches_the_builder() {
+    let from_macro = circuit! {
+        qubits 3;
+        h 0;
+        cx 0 1;
+        rz(std::f64::consts::PI / 3.0) 1;
+        ry(0.4) 2;
+        measure_at(0.7) 2;
+        measure 0;
+        measure 1;
+    };
+
+    let from_builder = CircuitBuilder::new(3)
+        .h(0)
+        .cx(0, 1)
+        .rz(std::f64::consts::PI / 3.0, 1)
+        .ry(0.4, 2)
+        .measure_at_angle(0.7, 2)
+        .measure(0)
+        .measure(1)
+        .build();
+
+    assert_eq!(
+        Experiment::new(vec![ZERO.clone(); 3], from_macro).exact_distribution(),
+        Experiment::new(vec![ZERO.clone(); 3], from_builder).exact_distribution()
+    );
+}
+
+#[test]
+fn named_measurements_and_conditionals() {
+    let teleport = circuit! {
+        qubits 3;
+        h 1;
+        cx 1 2;
+        cx 0 1;
+        h 0;
+        m0 = measure_hidden 1;
+        m1 = measure_hidden 0;
+        if m0 { x 2 }
+        if m1 { z 2; }
+    };
+
+    let experiment = Experiment::new(vec![ONE.clone(), ZERO.clone(), ZERO.clone()], circuit! {
+        qubits 3;
+        sub(teleport) 0;
+        measure 2;
+    });
+
+    let distribution = experiment.exact_distribution();
+
+    assert_eq!(distribution.len(), 1);
+    assert!((distribution["1"] - 1.0).abs() < 1e-12);
+}
+
+#[test]
+fn arbitrary_gates() {
+    let offset = 1;
+
+    let instructions = circuit! {
+        qubits 3;
+        x 2;
+        gate(CNOT) 2, 0;
+        swap offset 2;
+        measure 0;
+        measure 1;
+        measure 2;
+    };
+
+    let distribution = Experiment::new(vec![ZERO.clone(); 3], instructions).exact_distribution();
+
+    assert!((distribution["110"] - 1.0).abs() < 1e-12);
+}
@@ -0,0 +1,71 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+#[test]
+fn counts_gates_by_kind() {
+    let metrics = CircuitBuilder::new(3)
+        .h(0)
+        .cx(0, 1)
+        .cx(2, 0)
+        .t(1)
+        .tdg(2)
+        .t(2)
+        .u3(0.3, 0.2, 0.1, 0)
+        .swap(0, 2)
+        .measure(0)
+        .measure(1)
+        .experiment()
+        .metrics();
+
+    // The distant and the reversed CNOT are still CNOTs, T T† on qubit 2 are both counted
+    assert_eq!(metrics.count("CNOT"), 2);
+    assert_eq!(metrics.count("SWAP"), 1);
+    assert_eq!(metrics.count("U3"), 1);
+    assert_eq!(metrics.t_count, 3);
+    assert_eq!(metrics.two_qubit_gates, 3);
+    assert_eq!(metrics.gates, 8);
+    assert_eq!(metrics.measurements, 2);
+}
+
+#[test]
+fn depth_and_critical_path() {
+    let metrics = CircuitBuilder::new(4).h(0).h(1).h(2).cx(0, 1).x(3).cx(1, 2).measure(2).experiment().metrics();
+
+    assert_eq!(metrics.depth, 4);
+    assert_eq!(metrics.qubit_depths, vec![2, 3, 4, 1]);
+
+    let path: Vec<String> = metrics.critical_path.iter().map(|instruction| format!("{:?}", instruction)).collect();
+
+    assert_eq!(path.len(), 4);
+    assert!(path[0].contains("H") && path[1].contains("CNOT") && path[2].contains("CNOT") && path[3].contains("Measure 2"), "{:?}", path);
+}
+
+#[test]
+fn conditional_gates_wait_for_their_measurement() {
+    // The correction on qubit 2 can't start before qubit 0 is measured, however free qubit 2 is
+    let metrics = CircuitBuilder::new(3)
+        .h(0)
+        .t(0)
+        .h(0)
+        .measure_hidden(0)
+        .when(0, |c| c.x(2))
+        .measure(2)
+        .experiment()
+        .metrics();
+
+    assert_eq!(metrics.depth, 6);
+    assert_eq!(metrics.qubit_depths, vec![4, 0, 6]);
+    assert_eq!(metrics.critical_path.len(), 6);
+    assert_eq!(metrics.count("X"), 1);
+}
+
+#[test]
+fn repeated_blocks_count_every_repetition() {
+    let body = CircuitBuilder::new(2).h(0).cz(0, 1).build();
+    let metrics = CircuitBuilder::new(2).repeat(&body, 0, 3).experiment().metrics();
+
+    assert_eq!(metrics.count("H"), 3);
+    assert_eq!(metrics.two_qubit_gates, 3);
+    assert_eq!(metrics.depth, 6);
+    assert!(CircuitBuilder::new(2).experiment().metrics().critical_path.is_empty());
+}
@@ -0,0 +1,77 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+fn gates(experiment: &Experiment) -> usize {
+    experiment.instructions().iter().filter(|instruction| matches!(instruction, Instruction::Gate(_, _))).count()
+}
+
+#[test]
+fn cancels_inverse_pairs() {
+    let mut experiment = CircuitBuilder::new(3)
+        .h(0)
+        .x(1)
+        .h(0)
+        .cx(1, 2)
+        .cx(1, 2)
+        .x(1)
+        .h(2)
+        .x(2)
+        .x(2)
+        .h(2)
+        .measure(0)
+        .experiment();
+
+    // The xs on qubit 1 only meet once the cnots between them cancel
+    assert_eq!(experiment.optimize(), 10);
+    assert_eq!(gates(&experiment), 0);
+}
+
+#[test]
+fn fuses_single_qubit_runs() {
+    let circuit = CircuitBuilder::new(2).h(0).t(0).s(1).rx(0.3, 0).cx(0, 1).ry(0.2, 1).rz(0.4, 1).build();
+    let mut experiment = Experiment::new(vec![ZERO.clone(); 2], circuit.clone());
+
+    assert_eq!(experiment.optimize(), 3);
+    assert_eq!(gates(&experiment), 4);
+    assert!(check_equivalence(&circuit, experiment.instructions(), 2, 0.999).is_equivalent());
+}
+
+#[test]
+fn measurements_are_barriers() {
+    let mut experiment = CircuitBuilder::new(1).h(0).measure(0).h(0).measure(0).experiment();
+
+    assert_eq!(experiment.optimize(), 0);
+
+    // The rotation back from the first angled measurement fuses into the rotation for the
+    // second, and the trailing rotation back has nothing to fuse with
+    let mut angled = Experiment::new(vec![ZERO.clone()], vec![
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::MeasureAtAngle(0.4, 0, true),
+        Instruction::MeasureAtAngle(1.3, 0, true)
+    ]);
+
+    let before = angled.exact_distribution();
+
+    assert_eq!(angled.optimize(), 2);
+
+    for (outcome, probability) in angled.exact_distribution() {
+        assert!((before[&outcome] - probability).abs() < 1e-12);
+    }
+}
+
+#[test]
+fn optimizes_conditional_bodies() {
+    let mut experiment = CircuitBuilder::new(2)
+        .h(0)
+        .measure(0)
+        .when(0, |c| c.x(1).z(1).z(1).x(1).h(1))
+        .measure(1)
+        .experiment();
+
+    assert_eq!(experiment.optimize(), 4);
+
+    let distribution = experiment.exact_distribution();
+
+    assert!((distribution["00"] - 0.5).abs() < 1e-12);
+    assert!((distribution["11"] - 0.25).abs() < 1e-12);
+}
@@ -0,0 +1,62 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+fn teleport() -> Experiment {
+    let mut qubit = ONE.clone();
+
+    pass_gate(&mut qubit, &HADAMARD);
+
+    Experiment::new(vec![
+        qubit,
+        ZERO.clone(),
+        ZERO.clone()
+    ], vec![
+        Instruction::Gate(HADAMARD.clone(), 1),
+        Instruction::Gate(CNOT.clone(), 1),
+        Instruction::Gate(CNOT.clone(), 0),
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Measure(1, true),
+        Instruction::Measure(0, true),
+        Instruction::Dependent(Box::new(Instruction::Gate(PAULIX.clone(), 2)), 0),
+        Instruction::Dependent(Box::new(Instruction::Gate(PAULIZ.clone(), 2)), 1),
+        Instruction::Measure(2, true)
+    ])
+}
+
+fn bell_pair() -> Experiment {
+    Experiment::new(vec![
+        ZERO.clone(),
+        ZERO.clone()
+    ], vec![
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Gate(CNOT.clone(), 0),
+        Instruction::Measure(0, true),
+        Instruction::Measure(1, true)
+    ])
+}
+
+#[test]
+fn seeded_runs_ignore_thread_count() {
+    for experiment in [teleport(), bell_pair()] {
+        let single = experiment.average_out_with(5000, 1, Some(42));
+
+        assert_eq!(single.shots(), 5000);
+
+        for threads in [2, 3, 8] {
+            assert_eq!(experiment.average_out_with(5000, threads, Some(42)), single);
+        }
+    }
+}
+
+#[test]
+fn parallel_counts_match_distribution() {
+    let experiment = teleport();
+    let counts = experiment.average_out_with(20000, 4, Some(7));
+
+    assert_eq!(counts.shots(), 20000);
+    assert!(counts.total_variation_test(&experiment.exact_distribution()).passes(0.99));
+    assert_ne!(
+        experiment.average_out_with(2000, 4, Some(1)),
+        experiment.average_out_with(2000, 4, Some(2))
+    );
+}
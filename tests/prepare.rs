@@ -0,0 +1,99 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+fn amplitudes(qubits: usize, seed: usize) -> Vec<[f64; 2]> {
+    let state: Vec<[f64; 2]> = (0..1 << qubits).map(|i| {
+        let x = ((seed * 31 + i * 17) as f64 * 0.731).sin();
+
+        [x, ((seed + i * 5) as f64 * 1.37).cos() * 0.8]
+    }).collect();
+
+    let length = state.iter().map(|z| z[0] * z[0] + z[1] * z[1]).sum::<f64>().sqrt();
+
+    state.iter().map(|z| [z[0] / length, z[1] / length]).collect()
+}
+
+// Runs a circuit from |0...0⟩ and compares the result with the state, up to a global phase
+fn assert_prepares(circuit: &Vec<Instruction>, state: &Vec<[f64; 2]>) {
+    let qubits = (state.len() as f64).log2() as usize;
+    let unitary = circuit_unitary(circuit, qubits);
+    let prepared: Vec<[f64; 2]> = unitary.iter().map(|row| row[0]).collect();
+
+    let overlap = prepared.iter().zip(state.iter()).fold([0.0, 0.0], |sum, (a, b)| {
+        [sum[0] + a[0] * b[0] + a[1] * b[1], sum[1] + a[0] * b[1] - a[1] * b[0]]
+    });
+
+    assert!((overlap[0].hypot(overlap[1]) - 1.0).abs() < 1e-9, "{:?} != {:?}", prepared, state);
+}
+
+#[test]
+fn entangled_initial_states() {
+    let h = 0.5f64.sqrt();
+    let mut ghz = vec![[0.0, 0.0]; 8];
+
+    ghz[0] = [h, 0.0];
+    ghz[7] = [0.0, h];
+
+    let distribution = CircuitBuilder::new(3).measure(0).measure(1).measure(2).experiment_from_state(ghz.clone()).exact_distribution();
+
+    assert!((distribution["000"] - 0.5).abs() < 1e-12 && (distribution["111"] - 0.5).abs() < 1e-12);
+    assert_eq!(distribution.len(), 2);
+
+    let experiment = Experiment::from_state(ghz.clone(), vec![]);
+
+    assert_eq!(experiment.qubits(), 3);
+    assert_eq!(experiment.initial_state(), &ghz);
+}
+
+#[test]
+fn prepares_arbitrary_states() {
+    for qubits in 1..5 {
+        for seed in 0..3 {
+            let state = amplitudes(qubits, seed);
+
+            assert_prepares(&prepare_state(&state), &state);
+        }
+    }
+
+    // Basis states and real states, where most of the rotations vanish
+    let mut basis = vec![[0.0, 0.0]; 16];
+
+    basis[11] = [1.0, 0.0];
+    assert_prepares(&prepare_state(&basis), &basis);
+
+    let real: Vec<[f64; 2]> = (0..8).map(|i| [(i as f64 - 3.5) / 42f64.sqrt(), 0.0]).collect();
+
+    assert_prepares(&prepare_state(&real), &real);
+    assert!(prepare_state(&vec![[1.0, 0.0], [0.0, 0.0], [0.0, 0.0], [0.0, 0.0]]).is_empty());
+}
+
+#[test]
+fn prepared_states_match_initial_states() {
+    let state = amplitudes(3, 4);
+    let measured = CircuitBuilder::new(4).h(3).cx(0, 3).ry(0.4, 1).measure(0).measure(1).measure(2).measure(3).build();
+
+    let direct = Experiment::from_state(tensor_product_vector(vec![&state, &ZERO]), measured.clone());
+    let prepared = CircuitBuilder::new(4).prepare(&state, 0).sub(&measured, 0).experiment();
+
+    let (before, after) = (direct.exact_distribution(), prepared.exact_distribution());
+
+    for key in before.keys().chain(after.keys()) {
+        let (p, q) = (before.get(key).unwrap_or(&0.0), after.get(key).unwrap_or(&0.0));
+
+        assert!((p - q).abs() < 1e-9, "{}: {} != {}", key, p, q);
+    }
+
+    // Routing carries the initial state over to the physical qubits
+    let routed = route(&direct, &CouplingMap::line(5));
+    let after = routed.experiment.exact_distribution();
+
+    for (key, p) in before.iter() {
+        assert!((p - after.get(key).unwrap_or(&0.0)).abs() < 1e-9, "{}", key);
+    }
+}
+
+#[test]
+#[should_panic(expected = "should be normalized")]
+fn initial_states_have_to_be_normalized() {
+    Experiment::from_state(vec![[1.0, 0.0], [1.0, 0.0]], vec![]);
+}
@@ -0,0 +1,64 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+#[test]
+fn register_slicing() {
+    let registers = Register::allocate(&[("alice", 2), ("bob", 3)]);
+    let bob = &registers[1];
+
+    assert_eq!(bob.qubits(), &vec![2, 3, 4]);
+
+    let tail = bob.slice(1..3);
+
+    assert_eq!(tail.qubits(), &vec![3, 4]);
+    assert_eq!(tail.qubit(0), 3);
+    assert_eq!(tail.label(0), "bob[1]");
+}
+
+#[test]
+fn labels_tell_repeated_measurements_apart() {
+    let registers = Register::allocate(&[("alice", 1), ("bob", 1)]);
+    let (alice, bob) = (&registers[0], &registers[1]);
+
+    // bob gets measured twice, flipped in between
+    let experiment = CircuitBuilder::new(2)
+        .x(alice.qubit(0))
+        .measure_register(alice)
+        .measure_as(bob.qubit(0), "bob_m1")
+        .x(bob.qubit(0))
+        .measure_as(bob.qubit(0), "bob_m2")
+        .experiment();
+
+    let counts = experiment.average_out(100);
+
+    assert_eq!(counts.labels(), vec!["alice[0]", "bob_m1", "bob_m2"]);
+    assert_eq!(counts.qubits(), &vec![0, 1, 1]);
+    assert_eq!(counts.position("bob_m2"), Some(2));
+    assert_eq!(counts.to_string(), "Measurements: [ alice[0]=1, bob_m1=0, bob_m2=1 ] => 100.00%");
+    assert_eq!(counts.marginal(&[2]).labels(), vec!["bob_m2"]);
+
+    let (_, measurements) = experiment.run();
+
+    assert_eq!(measurements[2], Measurement(1, true, Some("bob_m2".to_string())));
+}
+
+#[test]
+fn labels_survive_simplification() {
+    // A labeled angled measurement inside a shifted sub circuit still reports its label
+    let inner = vec![
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Labeled("x_basis".to_string(), Box::new(Instruction::MeasureAtAngle(std::f64::consts::FRAC_PI_2, 0, true)))
+    ];
+
+    let experiment = Experiment::new(vec![ZERO.clone(); 2], vec![
+        Instruction::Circuit(inner, 1),
+        Instruction::Measure(0, true)
+    ]);
+
+    let counts = experiment.average_out(100);
+
+    assert_eq!(counts.labels(), vec!["x_basis", "0"]);
+    assert_eq!(counts.qubits(), &vec![1, 0]);
+    assert_eq!(counts.counts().len(), 1);
+    assert_eq!(experiment.exact_distribution().len(), 1);
+}
@@ -0,0 +1,121 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+fn assert_close(a: &Vec<Vec<[f64; 2]>>, b: &Vec<Vec<[f64; 2]>>) {
+    for (row_a, row_b) in a.iter().zip(b.iter()) {
+        for (x, y) in row_a.iter().zip(row_b.iter()) {
+            assert!((x[0] - y[0]).abs() < 1e-9 && (x[1] - y[1]).abs() < 1e-9, "{} != {}", print_matrix(a), print_matrix(b));
+        }
+    }
+}
+
+fn grover_iteration() -> Vec<Instruction> {
+    // Marks |101⟩, then reflects about the uniform superposition
+    let flip_zero = vec![vec![[-1.0, 0.0], [0.0, 0.0]], vec![[0.0, 0.0], [1.0, 0.0]]];
+
+    let mut instructions = vec![Instruction::Gate(PAULIZ.clone(), 2).controlled(vec![0, 1], vec![true, false])];
+
+    instructions.extend((0..3).map(|q| Instruction::Gate(HADAMARD.clone(), q)));
+    instructions.push(Instruction::Gate(flip_zero, 2).controlled(vec![0, 1], vec![false, false]));
+    instructions.extend((0..3).map(|q| Instruction::Gate(HADAMARD.clone(), q)));
+
+    instructions
+}
+
+#[test]
+fn gate_powers() {
+    let root_x = matrix_pow(&PAULIX, 0.5);
+
+    assert_close(&matrix_multiply(&root_x, &root_x), &PAULIX);
+    assert_close(&matrix_pow(&SGATE, 0.5), &TGATE);
+    assert_close(&matrix_pow(&PAULIZ, 0.5), &SGATE);
+    assert_close(&matrix_pow(&rx(0.4), 2.5), &rx(1.0));
+    assert_close(&matrix_pow(&HADAMARD, 3.0), &HADAMARD);
+    assert_close(&matrix_pow(&TGATE, -2.0), &SDAGGER);
+    assert_close(&matrix_pow(&CNOT, 0.5), &matrix_pow(&matrix_pow(&CNOT, 0.25), 2.0));
+}
+
+#[test]
+fn powers_with_look_alike_eigenvalues() {
+    // cos φ + 0.577215 sin φ is the same for both eigenphases, which the first mix can't tell apart
+    let offset = 0.577215f64.atan();
+    let diagonal = |k: f64| vec![
+        vec![[(k * (offset + 0.9)).cos(), (k * (offset + 0.9)).sin()], [0.0, 0.0]],
+        vec![[0.0, 0.0], [(k * (offset - 0.9)).cos(), (k * (offset - 0.9)).sin()]]
+    ];
+    let rotated = |k: f64| matrix_multiply(&HADAMARD, &matrix_multiply(&diagonal(k), &HADAMARD));
+
+    assert_close(&matrix_pow(&rotated(1.0), 0.5), &rotated(0.5));
+    assert_close(&matrix_pow(&rotated(1.0), -1.5), &rotated(-1.5));
+}
+
+#[test]
+fn grover_iterations() {
+    let mut instructions: Vec<Instruction> = (0..3).map(|q| Instruction::Gate(HADAMARD.clone(), q)).collect();
+
+    instructions.push(Instruction::Repeat(Box::new(Instruction::Circuit(grover_iteration(), 0)), 2));
+    instructions.extend((0..3).map(|q| Instruction::Measure(q, true)));
+
+    let experiment = Experiment::new(vec![ZERO.clone(); 3], instructions);
+    let expected = (5.0 * (1.0 / 8.0_f64).sqrt().asin()).sin().powf(2.0);
+
+    assert!(experiment.is_batchable());
+    assert!((experiment.exact_distribution()["101"] - expected).abs() < 1e-9);
+
+    let iteration = grover_iteration();
+    let from_macro = Experiment::new(vec![ZERO.clone(); 3], circuit! {
+        qubits 3;
+        h 0;
+        h 1;
+        h 2;
+        repeat(iteration, 2) 0;
+        measure 0;
+        measure 1;
+        measure 2;
+    });
+
+    assert!((from_macro.exact_distribution()["101"] - expected).abs() < 1e-9);
+}
+
+#[test]
+fn repeated_measurements_get_their_own_numbers() {
+    // Measures a fresh |+⟩ and resets the qubit every time, which only works if each copy's
+    // Dependent looks at its own measurement
+    let round = CircuitBuilder::new(1).h(0).measure(0).when(0, |c| c.x(0)).build();
+
+    let experiment = CircuitBuilder::new(1).repeat(&round, 0, 3).experiment();
+    let distribution = experiment.exact_distribution();
+
+    assert_eq!(distribution.len(), 8);
+    assert!(distribution.values().all(|p| (p - 0.125).abs() < 1e-12));
+}
+
+#[test]
+fn repeated_conditions_look_at_their_own_round() {
+    // Each round resets the qubit it measured, so the second measurement of every round reads 0
+    let round = CircuitBuilder::new(1).h(0).measure(0).when(0, |c| c.x(0)).measure(0).build();
+    let experiment = CircuitBuilder::new(1).repeat(&round, 0, 3).experiment();
+
+    for (bitstring, p) in experiment.exact_distribution() {
+        let bits: Vec<char> = bitstring.chars().collect();
+
+        assert_eq!(bits.len(), 6);
+        assert!(bits.iter().skip(1).step_by(2).all(|&bit| bit == '0'), "{} has probability {}", bitstring, p);
+    }
+}
+
+#[test]
+fn circuit_powers() {
+    let circuit = Instruction::Circuit(CircuitBuilder::new(2).h(0).t(0).cx(0, 1).ry(0.3, 1).build(), 0);
+
+    let state = tensor_product_vector(vec![&ZERO, &ONE]);
+    let experiment = Experiment::new(vec![ZERO.clone(), ONE.clone()], vec![circuit.pow(3.0), circuit.pow(-3.0)]);
+
+    assert!(fidelity(&experiment.run().0, &state) > 1.0 - 1e-12);
+}
+
+#[test]
+#[should_panic(expected = "only single gates have fractional powers")]
+fn fractional_circuit_power() {
+    Instruction::Circuit(vec![Instruction::Gate(HADAMARD.clone(), 0)], 0).pow(0.5);
+}
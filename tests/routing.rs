@@ -0,0 +1,139 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+use rand::{SeedableRng, rngs::StdRng};
+
+fn assert_same_distribution(a: &Experiment, b: &Experiment) {
+    let (before, after) = (a.exact_distribution(), b.exact_distribution());
+
+    for key in before.keys().chain(after.keys()) {
+        let (p, q) = (before.get(key).unwrap_or(&0.0), after.get(key).unwrap_or(&0.0));
+
+        assert!((p - q).abs() < 1e-9, "{}: {} != {}", key, p, q);
+    }
+}
+
+// On a line, gates only ever spread over neighbouring qubits
+fn assert_nearest_neighbour(experiment: &Experiment) {
+    for instruction in experiment.instructions() {
+        if let Instruction::Gate(gate, _) = instruction {
+            assert!(gate.len() <= 4, "{:?} isn't between neighbours", instruction);
+        }
+    }
+}
+
+#[test]
+fn coupling_maps_from_files() {
+    let path = std::env::temp_dir().join("quantum_sim_coupling_map.txt");
+
+    std::fs::write(&path, "# a T shape\n0 1\n1-2   # middle\n\n1 3\n").unwrap();
+
+    let coupling = CouplingMap::from_file(path.to_str().unwrap());
+
+    assert_eq!(coupling.qubits(), 4);
+    assert!(coupling.connected(2, 1) && !coupling.connected(0, 3));
+    assert_eq!(coupling.distance(0, 3), 2);
+    assert_eq!(coupling.neighbours(1), vec![0, 2, 3]);
+
+    assert_eq!(CouplingMap::grid(2, 3).distance(0, 5), 3);
+    assert_eq!(CouplingMap::ring(6).distance(0, 5), 1);
+}
+
+#[test]
+fn routes_onto_a_line() {
+    let experiment = CircuitBuilder::new(5)
+        .h(0)
+        .cx(0, 4)
+        .cx(4, 2)
+        .ry(0.7, 1)
+        .cz(1, 3)
+        .cx(0, 3)
+        .swap(2, 0)
+        .measure(0).measure(1).measure(2).measure(3).measure(4)
+        .experiment();
+
+    let routed = route(&experiment, &CouplingMap::line(5));
+
+    assert!(routed.swaps > 0);
+    assert_ne!(routed.initial_layout, routed.final_layout);
+    assert_nearest_neighbour(&routed.experiment);
+    assert_same_distribution(&experiment, &routed.experiment);
+}
+
+#[test]
+fn measurements_map_back_to_logical_qubits() {
+    let experiment = CircuitBuilder::new(3).x(0).cx(0, 2).h(1).measure(2).measure(0).measure(1).experiment();
+    let routed = route_with_layout(&experiment, &CouplingMap::line(4), Layout::new(vec![3, 1, 0]));
+
+    assert_nearest_neighbour(&routed.experiment);
+
+    let shot = routed.experiment.shot_with(&mut StdRng::seed_from_u64(5));
+    let logical = routed.logical_measurements(&shot.measurements);
+
+    assert_eq!(logical.iter().map(|m| m.0).collect::<Vec<usize>>(), vec![2, 0, 1]);
+    assert!(logical[0].1 && logical[1].1);
+    assert_eq!(routed.final_layout.logical(routed.physical(shot.measurements[2].0)), Some(1));
+}
+
+#[test]
+fn only_used_qubits_get_simulated() {
+    // A 5x5 grid would be 2^25 amplitudes, the routed experiment only needs the corner it uses
+    let experiment = CircuitBuilder::new(3).h(0).cx(0, 1).cx(1, 2).cx(0, 2).measure(0).measure(1).measure(2).experiment();
+    let routed = route_with_layout(&experiment, &CouplingMap::grid(5, 5), Layout::new(vec![0, 1, 6]));
+
+    assert!(routed.experiment.qubits() < 6, "{} qubits", routed.experiment.qubits());
+    assert!(routed.physical_qubits.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(routed.physical(0), 0);
+    assert_same_distribution(&experiment, &routed.experiment);
+
+    let shot = routed.experiment.shot_with(&mut StdRng::seed_from_u64(2));
+
+    assert_eq!(routed.logical_measurements(&shot.measurements).iter().map(|m| m.0).collect::<Vec<usize>>(), vec![0, 1, 2]);
+}
+
+#[test]
+fn forward_backward_passes_find_a_layout() {
+    // The gates only ever pair up 0-3, 3-1 and 1-2, which fits a line once the qubits are
+    // in the order 0 3 1 2
+    let experiment = CircuitBuilder::new(4)
+        .cx(0, 3).cx(3, 1).cx(1, 2)
+        .cx(0, 3).cx(3, 1).cx(1, 2)
+        .cx(0, 3).cx(3, 1).cx(1, 2)
+        .measure(0).measure(1).measure(2).measure(3)
+        .experiment();
+
+    let coupling = CouplingMap::line(4);
+    let trivial = route_with_layout(&experiment, &coupling, Layout::trivial(4));
+    let routed = route(&experiment, &coupling);
+
+    assert!(routed.swaps < trivial.swaps, "{} SWAPs from the passes, {} from the trivial layout", routed.swaps, trivial.swaps);
+    assert_nearest_neighbour(&routed.experiment);
+    assert_same_distribution(&experiment, &routed.experiment);
+}
+
+#[test]
+fn conditional_bodies_leave_the_layout_alone() {
+    // Teleports qubit 0 onto qubit 2 with the corrections after the measurements
+    let experiment = CircuitBuilder::new(3)
+        .ry(1.1, 0)
+        .h(1)
+        .cx(1, 2)
+        .cx(0, 1)
+        .h(0)
+        .measure_hidden(0)
+        .measure_hidden(1)
+        .when(1, |c| c.cx(0, 2).x(2))
+        .when(0, |c| c.z(2))
+        .measure(2)
+        .experiment();
+
+    let routed = route(&experiment, &CouplingMap::line(3));
+
+    assert_nearest_neighbour(&routed.experiment);
+    assert_same_distribution(&experiment, &routed.experiment);
+}
+
+#[test]
+#[should_panic(expected = "don't fit on a device")]
+fn too_many_qubits() {
+    route(&CircuitBuilder::new(4).cx(0, 3).experiment(), &CouplingMap::line(3));
+}
@@ -0,0 +1,75 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+fn names(moments: &Vec<Vec<Instruction>>) -> Vec<Vec<String>> {
+    moments.iter().map(|moment| moment.iter().map(|instruction| format!("{:?}", instruction)).collect()).collect()
+}
+
+#[test]
+fn asap_and_alap() {
+    let experiment = CircuitBuilder::new(3).h(0).x(2).cx(0, 1).cx(1, 2).t(0).experiment();
+
+    let asap = names(&experiment.moments(SchedulePolicy::ASAP));
+    let alap = names(&experiment.moments(SchedulePolicy::ALAP));
+
+    // X on 2 isn't in a hurry, T on 0 has to wait for the first CNOT either way
+    assert_eq!(asap.len(), 3);
+    assert_eq!(asap.iter().map(|moment| moment.len()).collect::<Vec<usize>>(), vec![2, 1, 2]);
+    assert!(asap[0][1].contains("X") && asap[2][1].contains("T"));
+
+    assert_eq!(alap.len(), 3);
+    assert_eq!(alap.iter().map(|moment| moment.len()).collect::<Vec<usize>>(), vec![1, 2, 2]);
+    assert!(alap[1][0].contains("X") && alap[2][1].contains("T"));
+}
+
+#[test]
+fn moments_never_share_qubits() {
+    let experiment = CircuitBuilder::new(5)
+        .h(0).h(3).cx(0, 4).swap(1, 2).ry(0.3, 3).cz(2, 3).x(1).cx(4, 1).measure(0).measure(2)
+        .experiment();
+
+    for policy in [SchedulePolicy::ASAP, SchedulePolicy::ALAP] {
+        let moments = experiment.moments(policy);
+
+        for moment in moments.iter() {
+            let widths: usize = moment.iter().map(|instruction| match instruction {
+                Instruction::Gate(gate, _) if gate.len() > 2 => 2,
+                _ => 1
+            }).sum();
+
+            assert!(widths <= 5);
+        }
+
+        // Running the moments one after the other is the same experiment
+        let flat = Experiment::new(vec![ZERO.clone(); 5], moments.into_iter().flatten().collect());
+
+        assert_eq!(flat.exact_distribution(), experiment.exact_distribution());
+    }
+
+    assert_eq!(experiment.metrics().depth, experiment.moments(SchedulePolicy::ASAP).len());
+}
+
+#[test]
+fn measurements_and_conditions_stay_in_order() {
+    // The second measurement could go first if it weren't for the numbering
+    let experiment = CircuitBuilder::new(2)
+        .h(0)
+        .t(0)
+        .h(0)
+        .measure(0)
+        .measure(1)
+        .when(0, |c| c.x(1).h(1))
+        .measure(1)
+        .experiment();
+
+    let moments = names(&experiment.moments(SchedulePolicy::ASAP));
+
+    assert_eq!(moments.len(), 7);
+    assert_eq!(moments[3], vec!["{Measure 0 @ |1⟩}", "{Measure 1 @ |1⟩}"]);
+
+    // The conditional block is split up, every part of it waiting for the measurement
+    assert!(moments[4][0].contains("X") && moments[4][0].contains("measurement #0"));
+    assert!(moments[5][0].contains("H") && moments[5][0].contains("measurement #0"));
+
+    assert!(CircuitBuilder::new(1).experiment().moments(SchedulePolicy::ALAP).is_empty());
+}
@@ -0,0 +1,104 @@
+extern crate quantum_sim;
+use std::collections::BTreeMap;
+
+use quantum_sim::*;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+fn close(a: f64, b: f64, tolerance: f64) -> bool {
+    (a - b).abs() < tolerance
+}
+
+// Shots drawn from the exact distribution with a fixed seed, so the checks can't flake
+fn seeded_counts(distribution: &BTreeMap<String, f64>, shots: usize, seed: u64) -> Counts {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut counts = Counts::new();
+
+    for _ in 0..shots {
+        let mut r = rng.gen::<f64>();
+        let (outcome, _) = distribution.iter().find(|(_, &p)| { r -= p; r < 0.0 }).or(distribution.iter().last()).unwrap();
+
+        counts.add(outcome.clone(), 1);
+    }
+
+    counts
+}
+
+#[test]
+fn intervals() {
+    assert!(close(normal_quantile(0.975), 1.959964, 1e-6));
+    assert!(close(normal_quantile(0.005), -2.575829, 1e-6));
+
+    let (low, high) = wilson_interval(50, 100, 0.95);
+    assert!(close(low, 0.4038, 1e-4) && close(high, 0.5962, 1e-4));
+
+    let (low, high) = clopper_pearson_interval(50, 100, 0.95);
+    assert!(close(low, 0.3983, 1e-4) && close(high, 0.6017, 1e-4));
+
+    let (low, high) = clopper_pearson_interval(0, 10, 0.95);
+    assert!(low == 0.0 && close(high, 0.3085, 1e-4));
+}
+
+#[test]
+fn goodness_of_fit() {
+    let mut counts = Counts::new();
+    counts.add(String::from("0"), 60);
+    counts.add(String::from("1"), 40);
+
+    let fair: BTreeMap<String, f64> = vec![(String::from("0"), 0.5), (String::from("1"), 0.5)].into_iter().collect();
+
+    let chi_square = counts.chi_square_test(&fair);
+
+    assert!(close(chi_square.statistic, 4.0, 1e-12));
+    assert_eq!(chi_square.degrees_of_freedom, 1);
+    assert!(close(chi_square.p_value, 0.0455, 1e-4));
+    assert!(chi_square.passes(0.99) && !chi_square.passes(0.95));
+
+    let only_zero: BTreeMap<String, f64> = vec![(String::from("0"), 1.0)].into_iter().collect();
+
+    assert_eq!(counts.chi_square_test(&only_zero).p_value, 0.0);
+    assert!(!counts.total_variation_test(&only_zero).passes(0.99));
+}
+
+#[test]
+fn exact_distributions() {
+    let bell = Experiment::new(vec![
+        ZERO.clone(),
+        ZERO.clone()
+    ], vec![
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Gate(CNOT.clone(), 0),
+        Instruction::Measure(0, true),
+        Instruction::Measure(1, true)
+    ]);
+
+    let distribution = bell.exact_distribution();
+
+    assert_eq!(distribution.len(), 2);
+    assert!(close(distribution["00"], 0.5, 1e-12) && close(distribution["11"], 0.5, 1e-12));
+
+    let counts = seeded_counts(&distribution, 2000, 7);
+
+    assert!(counts.total_variation_test(&distribution).passes(0.99));
+
+    // Hidden measurements and the corrections depending on them don't show up
+    let teleport = Experiment::new(vec![
+        ONE.clone(),
+        ZERO.clone(),
+        ZERO.clone()
+    ], vec![
+        Instruction::Gate(HADAMARD.clone(), 1),
+        Instruction::Gate(CNOT.clone(), 1),
+        Instruction::Gate(CNOT.clone(), 0),
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Measure(1, false),
+        Instruction::Measure(0, false),
+        Instruction::Dependent(Box::new(Instruction::Gate(PAULIX.clone(), 2)), 0),
+        Instruction::Dependent(Box::new(Instruction::Gate(PAULIZ.clone(), 2)), 1),
+        Instruction::Measure(2, true)
+    ]);
+
+    let distribution = teleport.exact_distribution();
+
+    assert_eq!(distribution.len(), 1);
+    assert!(close(distribution["1"], 1.0, 1e-12));
+}
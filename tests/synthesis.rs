@@ -0,0 +1,91 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+fn assert_synthesizes(gate: &Vec<Vec<[f64; 2]>>) -> UnitarySynthesis {
+    let qubits = (gate.len() as f64).log2() as usize;
+    let synthesis = synthesize_unitary(gate);
+    let rebuilt = circuit_unitary(&synthesis.circuit, qubits);
+
+    assert!(unitary_equiv_up_to_phase(&rebuilt, gate, 1e-8), "{} != {}", print_matrix(&rebuilt), print_matrix(gate));
+
+    // Besides single qubit gates there are only CNOTs, in either direction and spread out
+    for instruction in synthesis.circuit.iter() {
+        if let Instruction::Gate(matrix, _) = instruction {
+            let permutation = matrix.iter().flatten().all(|z| z[1] == 0.0 && (z[0] == 0.0 || z[0] == 1.0));
+
+            assert!(matrix.len() == 2 || permutation, "{:?}", instruction);
+        }
+    }
+
+    synthesis
+}
+
+fn scrambled(qubits: usize, seed: usize) -> Vec<Vec<[f64; 2]>> {
+    let mut builder = CircuitBuilder::new(qubits);
+
+    for layer in 0..qubits + 1 {
+        for q in 0..qubits {
+            let angle = (seed * 7 + layer * 3 + q) as f64 * 0.37;
+
+            builder = builder.u3(angle, 1.3 - angle, angle * 0.5, q);
+        }
+
+        for q in 0..qubits - 1 {
+            builder = if layer % 2 == 0 { builder.cx(q, q + 1) } else { builder.cz(q + 1, q) };
+        }
+    }
+
+    circuit_unitary(&builder.build(), qubits)
+}
+
+#[test]
+fn three_and_four_qubits() {
+    for seed in 0..3 {
+        assert!(assert_synthesizes(&scrambled(3, seed)).cnots <= 24);
+    }
+
+    assert!(assert_synthesizes(&scrambled(4, 1)).cnots <= 120);
+}
+
+#[test]
+fn structured_unitaries() {
+    // Degenerate cosine-sine values and blocks that are exactly zero
+    let toffoli = controlled_gate(&PAULIX, &[true, true]);
+    let blocks = tensor_product_matrix(vec![&HADAMARD, &SWAP, &rz(0.4)]);
+
+    assert_synthesizes(&toffoli);
+    assert_synthesizes(&blocks);
+    assert_synthesizes(&identity(8));
+    assert_synthesizes(&controlled_gate(&SWAP, &[false]));
+}
+
+#[test]
+fn small_gates() {
+    assert_eq!(assert_synthesizes(&TGATE).cnots, 0);
+    assert_eq!(assert_synthesizes(&SWAP).cnots, 3);
+    assert_eq!(assert_synthesizes(&tensor_product_matrix(vec![&HADAMARD, &PAULIY])).cnots, 0);
+}
+
+#[test]
+fn transpiles_custom_matrices() {
+    let experiment = Experiment::new(vec![ZERO.clone(); 3], vec![
+        Instruction::Gate(HADAMARD.clone(), 1),
+        Instruction::Gate(scrambled(3, 4), 0),
+        Instruction::Measure(0, true),
+        Instruction::Measure(1, true),
+        Instruction::Measure(2, true)
+    ]);
+
+    let transpiled = transpile(&experiment, &Target::ibm());
+    let (before, after) = (experiment.exact_distribution(), transpiled.exact_distribution());
+
+    for (key, p) in before.iter() {
+        assert!((p - after.get(key).unwrap_or(&0.0)).abs() < 1e-9);
+    }
+}
+
+#[test]
+#[should_panic(expected = "power of two")]
+fn sizes_have_to_be_powers_of_two() {
+    synthesize_unitary(&identity(6));
+}
@@ -0,0 +1,146 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+fn close(a: &Vec<Vec<[f64; 2]>>, b: &Vec<Vec<[f64; 2]>>) -> bool {
+    a.iter().flatten().zip(b.iter().flatten()).all(|(x, y)| (x[0] - y[0]).abs() < 1e-9 && (x[1] - y[1]).abs() < 1e-9)
+}
+
+// Whether a gate is one of the target's, rotations being recognized by their shape
+fn is_native(gate: &Vec<Vec<[f64; 2]>>, target: &Target) -> bool {
+    let supports = |native| target.supports(native);
+
+    if gate.len() == 2 {
+        let angles = euler_angles(gate, EulerBasis::U3);
+        let (c, s) = (gate[0][0][0], gate[1][0]);
+        let rotation = |matrix: Vec<Vec<[f64; 2]>>| close(gate, &matrix);
+
+        return (supports(NativeGate::X) && close(gate, &PAULIX))
+            || (supports(NativeGate::SX) && close(gate, &SXGATE))
+            || (supports(NativeGate::H) && close(gate, &HADAMARD))
+            || (supports(NativeGate::RX) && rotation(rx(2.0 * s[1].atan2(c) * -1.0)))
+            || (supports(NativeGate::RY) && rotation(ry(2.0 * s[0].atan2(c))))
+            || (supports(NativeGate::RZ) && rotation(rz(2.0 * gate[1][1][1].atan2(gate[1][1][0]))))
+            || (supports(NativeGate::U3) && rotation(u3(angles.theta, angles.phi, angles.lambda)));
+    }
+
+    let span = (gate.len() as f64).log2() as usize;
+
+    (0..span).any(|a| (0..span).filter(|&b| b != a).any(|b| {
+        (supports(NativeGate::CNOT) && close(gate, &embed_gate(&CNOT, &[a, b]).0)) || (supports(NativeGate::CZ) && close(gate, &embed_gate(&CZ, &[a, b]).0))
+    }))
+}
+
+fn assert_transpiles(experiment: &Experiment, target: &Target) -> Experiment {
+    let transpiled = transpile(experiment, target);
+
+    for instruction in transpiled.instructions() {
+        if let Instruction::Gate(gate, _) = instruction {
+            assert!(is_native(gate, target), "{:?} isn't in {:?}", instruction, target.gates());
+        }
+    }
+
+    let (before, after) = (experiment.exact_distribution(), transpiled.exact_distribution());
+
+    for key in before.keys().chain(after.keys()) {
+        let (p, q) = (before.get(key).unwrap_or(&0.0), after.get(key).unwrap_or(&0.0));
+
+        assert!((p - q).abs() < 1e-9, "{}: {} != {}", key, p, q);
+    }
+
+    transpiled
+}
+
+fn two_qubit_gates(experiment: &Experiment) -> usize {
+    experiment.instructions().iter().filter(|instruction| matches!(instruction, Instruction::Gate(gate, _) if gate.len() > 2)).count()
+}
+
+#[test]
+fn single_qubit_gates_in_every_basis() {
+    let experiment = CircuitBuilder::new(2)
+        .h(0)
+        .t(0)
+        .u3(0.4, -1.2, 2.2, 1)
+        .rx(0.9, 0)
+        .s(1)
+        .ry(2.5, 1)
+        .measure(0)
+        .measure(1)
+        .experiment();
+
+    let targets = [
+        Target::ibm(),
+        Target::rotations_cz(),
+        Target::new(&[NativeGate::U3, NativeGate::CNOT]),
+        Target::new(&[NativeGate::RZ, NativeGate::RY, NativeGate::CZ]),
+        Target::new(&[NativeGate::RZ, NativeGate::RX, NativeGate::CNOT]),
+        Target::new(&[NativeGate::RZ, NativeGate::H, NativeGate::CNOT]),
+        Target::new(&[NativeGate::RX, NativeGate::H, NativeGate::CZ])
+    ];
+
+    for target in targets.iter() {
+        assert_transpiles(&experiment, target);
+    }
+
+    // Diagonal gates are a single RZ
+    let phase = CircuitBuilder::new(1).t(0).s(0).measure(0).experiment();
+
+    assert_eq!(assert_transpiles(&phase, &Target::ibm()).instructions().len(), 2);
+}
+
+#[test]
+fn entangling_gates() {
+    let experiment = CircuitBuilder::new(3).h(0).cx(0, 2).swap(1, 2).ry(0.3, 1).cz(0, 1).measure(0).measure(1).measure(2).experiment();
+
+    let ibm = assert_transpiles(&experiment, &Target::ibm());
+
+    // The distant CNOT stays one, SWAP takes three
+    assert_eq!(two_qubit_gates(&ibm), 5);
+
+    let cz = assert_transpiles(&CircuitBuilder::new(2).h(0).cx(0, 1).measure(0).measure(1).experiment(), &Target::rotations_cz());
+
+    assert_eq!(two_qubit_gates(&cz), 1);
+}
+
+#[test]
+fn wider_gates() {
+    let toffoli = controlled_gate(&PAULIX, &[true, true]);
+    let experiment = CircuitBuilder::new(3).h(0).h(1).gate(&toffoli, &[0, 1, 2]).measure(0).measure(1).measure(2).experiment();
+
+    assert_transpiles(&experiment, &Target::ibm());
+
+    // Some three qubit unitary without any structure to it
+    let scrambled = circuit_unitary(&CircuitBuilder::new(3).u3(0.3, 1.1, -0.7, 0).cx(0, 1).ry(1.3, 2).cx(2, 0).u3(2.0, 0.4, 0.2, 1).cx(1, 2).rx(0.8, 0).build(), 3);
+    let experiment = Experiment::new(vec![ZERO.clone(); 3], vec![
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::Gate(scrambled, 0),
+        Instruction::Measure(0, true),
+        Instruction::Measure(1, true),
+        Instruction::Measure(2, true)
+    ]);
+
+    assert_transpiles(&experiment, &Target::rotations_cz());
+}
+
+#[test]
+fn measurements_and_conditionals() {
+    let experiment = Experiment::new(vec![ZERO.clone(); 2], vec![
+        Instruction::Gate(HADAMARD.clone(), 0),
+        Instruction::MeasureAtAngle(0.7, 0, true),
+        Instruction::Dependent(Box::new(Instruction::Gate(rx(1.1), 1)), 0),
+        Instruction::MeasureAtSpinVector(vec![[0.6, 0.0], [0.0, 0.8]], 1, true)
+    ]);
+
+    assert_transpiles(&experiment, &Target::ibm());
+}
+
+#[test]
+#[should_panic(expected = "can't make every single qubit gate")]
+fn targets_need_a_universal_single_qubit_set() {
+    Target::new(&[NativeGate::RZ, NativeGate::CNOT]);
+}
+
+#[test]
+#[should_panic(expected = "can't make every single qubit gate")]
+fn real_gates_arent_universal() {
+    Target::new(&[NativeGate::RY, NativeGate::H, NativeGate::CZ]);
+}
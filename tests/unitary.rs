@@ -0,0 +1,65 @@
+extern crate quantum_sim;
+use quantum_sim::*;
+
+fn assert_close(a: &Vec<Vec<[f64; 2]>>, b: &Vec<Vec<[f64; 2]>>) {
+    assert_eq!(a.len(), b.len());
+
+    for (row_a, row_b) in a.iter().zip(b.iter()) {
+        for (x, y) in row_a.iter().zip(row_b.iter()) {
+            assert!((x[0] - y[0]).abs() < 1e-12 && (x[1] - y[1]).abs() < 1e-12, "{} != {}", print_matrix(a), print_matrix(b));
+        }
+    }
+}
+
+// |x, y⟩ -> |x, y ⊕ f(x)⟩
+fn oracle_matrix(f: [bool; 2]) -> Vec<Vec<[f64; 2]>> {
+    (0..4).map(|row: usize| {
+        (0..4).map(|column: usize| {
+            let (x, y) = (column >> 1, column & 1);
+
+            if row == (x << 1 | (y ^ f[x] as usize)) { [1.0, 0.0] } else { [0.0, 0.0] }
+        }).collect()
+    }).collect()
+}
+
+#[test]
+fn deutsch_oracles_match_their_truth_tables() {
+    let oracles = vec![
+        ([false, false], vec![]),
+        ([true, true], vec![Instruction::Gate(PAULIX.clone(), 1)]),
+        ([false, true], vec![Instruction::Gate(CNOT.clone(), 0)]),
+        ([true, false], vec![Instruction::Gate(PAULIX.clone(), 1).controlled(vec![0], vec![false])])
+    ];
+
+    for (truth_table, oracle) in oracles {
+        assert_close(&circuit_unitary(&oracle, 2), &oracle_matrix(truth_table));
+    }
+}
+
+#[test]
+fn placement_and_repetition() {
+    let experiment = Experiment::new(vec![ZERO.clone(); 3], vec![
+        Instruction::Circuit(vec![Instruction::Gate(HADAMARD.clone(), 0)], 1)
+    ]);
+
+    assert_close(&experiment.unitary(), &tensor_product_matrix(vec![&IDENTITY, &HADAMARD, &IDENTITY]));
+
+    let round = vec![Instruction::Gate(HADAMARD.clone(), 0), Instruction::Gate(SGATE.clone(), 0)];
+    let repeated = vec![Instruction::Repeat(Box::new(Instruction::Circuit(round, 0)), 4)];
+
+    assert_close(&circuit_unitary(&repeated, 1), &matrix_pow(&matrix_multiply(&SGATE, &HADAMARD), 4.0));
+}
+
+#[test]
+fn unitary_is_unitary() {
+    let circuit = CircuitBuilder::new(3).h(0).cx(0, 2).t(1).swap(1, 2).ry(0.4, 0).build();
+    let u = circuit_unitary(&circuit, 3);
+
+    assert_close(&matrix_multiply(&dagger(&u), &u), &identity(8));
+}
+
+#[test]
+#[should_panic(expected = "only measurement free circuits have a unitary")]
+fn measurements_have_no_unitary() {
+    CircuitBuilder::new(1).h(0).measure(0).experiment().unitary();
+}